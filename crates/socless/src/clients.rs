@@ -1,82 +1,299 @@
+//! The AWS clients used by socless.
+//!
+//! Every request goes through the [`SoclessClients`] returned by [`get_or_init_clients`]:
+//! - clients set for the current task with [`with_clients`], e.g. assumed-role clients for a
+//!   cross-account integration
+//! - otherwise the global clients, set with [`set_clients`] or built on first use from the
+//!   environment (`AWS_PROFILE`, `AWS_REGION`, ...) and the endpoints in the [`SoclessConfig`]
+//!
+//! Each service can be pointed at its own local stand-in with `SOCLESS_DYNAMODB_ENDPOINT`,
+//! `SOCLESS_SFN_ENDPOINT` and `SOCLESS_S3_ENDPOINT`, falling back to `AWS_ENDPOINT_URL`.
+
+use crate::{
+    config::{get_or_init_config, AwsService, SoclessConfig},
+    errors::{SoclessError, SoclessResult},
+};
 use aws_sdk_dynamodb::Endpoint;
 use hyper::Uri;
+use std::future::Future;
 use tokio::sync::OnceCell;
 
 pub const AWS_ENDPOINT_URL: &str = "AWS_ENDPOINT_URL";
 
-pub static AWS_CONFIG_AND_URL: OnceCell<(aws_config::Config, Option<String>)> =
-    OnceCell::const_new();
-pub async fn get_or_init_aws_config_and_url() -> &'static (aws_config::Config, Option<String>) {
-    // You can select a profile by setting the `AWS_PROFILE` environment variable.
-    AWS_CONFIG_AND_URL
-        .get_or_init(|| async {
-            (
-                aws_config::load_from_env().await,
-                std::env::var(AWS_ENDPOINT_URL).ok(),
-            )
-        })
-        .await
+/// Build an SDK client from the shared `aws_config`, optionally sending its requests to `endpoint`.
+macro_rules! client_with_endpoint {
+    ($sdk:ident, $aws_config:expr, $endpoint:expr) => {
+        match $endpoint {
+            Some(uri) => $sdk::Client::from_conf(
+                $sdk::config::Builder::from($aws_config)
+                    .endpoint_resolver(Endpoint::immutable(uri))
+                    .build(),
+            ),
+            None => $sdk::Client::new($aws_config),
+        }
+    };
 }
 
-pub static DYNAMO_CLIENT: OnceCell<aws_sdk_dynamodb::Client> = OnceCell::const_new();
-pub async fn get_or_init_dynamo() -> &'static aws_sdk_dynamodb::Client {
-    DYNAMO_CLIENT
-        .get_or_init(|| async {
-            let (base_config, base_url) = get_or_init_aws_config_and_url().await;
-
-            if let Some(endpoint_url) = base_url {
-                aws_sdk_dynamodb::Client::from_conf(
-                    aws_sdk_dynamodb::config::Builder::from(base_config)
-                        .endpoint_resolver(Endpoint::immutable(
-                            endpoint_url.parse::<Uri>().expect("invald endpoint url"),
-                        ))
-                        .build(),
-                )
-            } else {
-                aws_sdk_dynamodb::Client::new(&base_config)
-            }
-        })
-        .await
+/// One client per AWS service used by socless. Clients are cheap to clone and share their
+/// connection pool.
+#[derive(Clone)]
+pub struct SoclessClients {
+    pub dynamo: aws_sdk_dynamodb::Client,
+    pub sfn: aws_sdk_sfn::Client,
+    pub s3: aws_sdk_s3::Client,
+    pub kms: aws_sdk_kms::Client,
 }
 
-pub static SFN_CLIENT: OnceCell<aws_sdk_sfn::Client> = OnceCell::const_new();
-pub async fn get_or_init_sfn() -> &'static aws_sdk_sfn::Client {
-    SFN_CLIENT
-        .get_or_init(|| async {
-            let (base_config, base_url) = get_or_init_aws_config_and_url().await;
-
-            if let Some(endpoint_url) = base_url {
-                aws_sdk_sfn::Client::from_conf(
-                    aws_sdk_sfn::config::Builder::from(base_config)
-                        .endpoint_resolver(Endpoint::immutable(
-                            endpoint_url.parse::<Uri>().expect("invald endpoint url"),
-                        ))
-                        .build(),
-                )
-            } else {
-                aws_sdk_sfn::Client::new(&base_config)
-            }
+impl SoclessClients {
+    /// Clients for the region, credentials and retry settings of `aws_config`, e.g.
+    /// ```ignore
+    /// let aws_config = aws_config::from_env()
+    ///     .region(Region::new("us-west-2"))
+    ///     .credentials_provider(assumed_role_provider)
+    ///     .load()
+    ///     .await;
+    /// let clients = SoclessClients::new(&aws_config);
+    /// ```
+    pub fn new(aws_config: &aws_config::Config) -> Self {
+        SoclessClients {
+            dynamo: aws_sdk_dynamodb::Client::new(aws_config),
+            sfn: aws_sdk_sfn::Client::new(aws_config),
+            s3: aws_sdk_s3::Client::new(aws_config),
+            kms: aws_sdk_kms::Client::new(aws_config),
+        }
+    }
+
+    /// Same as [`SoclessClients::new`], sending every request to `endpoint_url` (e.g. Localstack).
+    pub fn with_endpoint_url(
+        aws_config: &aws_config::Config,
+        endpoint_url: &str,
+    ) -> SoclessResult<Self> {
+        let uri = parse_endpoint_url(endpoint_url)?;
+        Self::with_endpoints(aws_config, |_| Ok(Some(uri.clone())))
+    }
+
+    /// Same as [`SoclessClients::new`], sending each service's requests to its endpoint in
+    /// `config`, see [`SoclessConfig::endpoint_url_for`].
+    pub fn from_config(
+        aws_config: &aws_config::Config,
+        config: &SoclessConfig,
+    ) -> SoclessResult<Self> {
+        Self::with_endpoints(aws_config, |service| {
+            config
+                .endpoint_url_for(service)
+                .map(parse_endpoint_url)
+                .transpose()
+        })
+    }
+
+    fn with_endpoints(
+        aws_config: &aws_config::Config,
+        endpoint: impl Fn(AwsService) -> SoclessResult<Option<Uri>>,
+    ) -> SoclessResult<Self> {
+        Ok(SoclessClients {
+            dynamo: client_with_endpoint!(
+                aws_sdk_dynamodb,
+                aws_config,
+                endpoint(AwsService::DynamoDb)?
+            ),
+            sfn: client_with_endpoint!(
+                aws_sdk_sfn,
+                aws_config,
+                endpoint(AwsService::StepFunctions)?
+            ),
+            // aws-sdk-s3 0.4 has no addressing setting and always sends path-style requests
+            // (`<endpoint>/<bucket>/<key>`), so S3 stand-ins like MinIO work without wildcard DNS
+            // for bucket subdomains. test_s3_endpoint_uses_path_style_requests checks it still does.
+            s3: client_with_endpoint!(aws_sdk_s3, aws_config, endpoint(AwsService::S3)?),
+            kms: client_with_endpoint!(aws_sdk_kms, aws_config, endpoint(AwsService::Kms)?),
         })
-        .await
+    }
+
+    /// Clients for the AWS environment variables and the endpoints in the [`SoclessConfig`].
+    pub async fn from_env() -> SoclessResult<Self> {
+        // You can select a profile by setting the `AWS_PROFILE` environment variable.
+        let aws_config = aws_config::load_from_env().await;
+        Self::from_config(&aws_config, get_or_init_config().await?)
+    }
+}
+
+fn parse_endpoint_url(endpoint_url: &str) -> SoclessResult<Uri> {
+    endpoint_url
+        .parse::<Uri>()
+        .map_err(|e| SoclessError::Config(format!("invalid endpoint url {}: {}", endpoint_url, e)))
 }
 
-pub static S3_CLIENT: OnceCell<aws_sdk_s3::Client> = OnceCell::const_new();
-pub async fn get_or_init_s3() -> &'static aws_sdk_s3::Client {
-    S3_CLIENT
-        .get_or_init(|| async {
-            let (base_config, base_url) = get_or_init_aws_config_and_url().await;
-
-            if let Some(endpoint_url) = base_url {
-                aws_sdk_s3::Client::from_conf(
-                    aws_sdk_s3::config::Builder::from(base_config)
-                        .endpoint_resolver(Endpoint::immutable(
-                            endpoint_url.parse::<Uri>().expect("invald endpoint url"),
-                        ))
-                        .build(),
-                )
-            } else {
-                aws_sdk_s3::Client::new(&base_config)
+pub static SOCLESS_CLIENTS: OnceCell<SoclessClients> = OnceCell::const_new();
+
+tokio::task_local! {
+    static TASK_CLIENTS: SoclessClients;
+}
+
+/// The clients set for the current task by [`with_clients`], otherwise the global clients.
+pub async fn get_or_init_clients() -> SoclessResult<SoclessClients> {
+    current_client(Clone::clone).await
+}
+
+/// Replace the global clients built from the environment.
+///
+/// Returns `false` if the clients were already initialized, in which case `clients` is dropped.
+pub fn set_clients(clients: SoclessClients) -> bool {
+    SOCLESS_CLIENTS.set(clients).is_ok()
+}
+
+/// Run `f` with `clients` in place of the global clients. Only socless calls made by `f` itself
+/// are affected, tasks it spawns use the global clients.
+/// ### Example
+/// ```ignore
+/// let clients = SoclessClients::new(&assumed_role_config);
+/// with_clients(clients, save_to_vault(report, Some("text/csv"))).await?;
+/// ```
+pub async fn with_clients<F: Future>(clients: SoclessClients, f: F) -> F::Output {
+    TASK_CLIENTS.scope(clients, f).await
+}
+
+async fn current_client<T>(client: fn(&SoclessClients) -> T) -> SoclessResult<T> {
+    if let Ok(task_client) = TASK_CLIENTS.try_with(client) {
+        return Ok(task_client);
+    }
+
+    let clients = SOCLESS_CLIENTS
+        .get_or_try_init(SoclessClients::from_env)
+        .await?;
+    Ok(client(clients))
+}
+
+pub async fn get_or_init_dynamo() -> SoclessResult<aws_sdk_dynamodb::Client> {
+    current_client(|clients| clients.dynamo.clone()).await
+}
+
+pub async fn get_or_init_sfn() -> SoclessResult<aws_sdk_sfn::Client> {
+    current_client(|clients| clients.sfn.clone()).await
+}
+
+pub async fn get_or_init_s3() -> SoclessResult<aws_sdk_s3::Client> {
+    current_client(|clients| clients.s3.clone()).await
+}
+
+pub async fn get_or_init_kms() -> SoclessResult<aws_sdk_kms::Client> {
+    current_client(|clients| clients.kms.clone()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_types::{credentials::SharedCredentialsProvider, region::Region, Credentials};
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+
+    fn test_aws_config() -> aws_config::Config {
+        aws_config::Config::builder()
+            .region(Region::new("us-east-1"))
+            .credentials_provider(SharedCredentialsProvider::new(Credentials::from_keys(
+                "test", "test", None,
+            )))
+            .build()
+    }
+
+    /// Answer one HTTP request on a local port with an empty 200, sending back its request line
+    /// and `Host` header.
+    fn capture_one_request() -> (u16, mpsc::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut host = String::new();
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("host") {
+                        host = value.trim().to_owned();
+                    }
+                }
             }
-        })
-        .await
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                .unwrap();
+            sender.send((request_line.trim().to_owned(), host)).unwrap();
+        });
+
+        (port, receiver)
+    }
+
+    #[test]
+    fn test_clients_reject_invalid_endpoint_url() {
+        assert!(matches!(
+            SoclessClients::with_endpoint_url(&test_aws_config(), "http://local host"),
+            Err(SoclessError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_clients_from_config_with_per_service_endpoints() {
+        let config = SoclessConfig::builder()
+            .dynamodb_endpoint_url("http://localhost:8000")
+            .s3_endpoint_url("http://localhost:9000")
+            .build()
+            .unwrap();
+        assert!(SoclessClients::from_config(&test_aws_config(), &config).is_ok());
+
+        let invalid = SoclessConfig {
+            s3_endpoint_url: Some("http://minio host".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            SoclessClients::from_config(&test_aws_config(), &invalid),
+            Err(SoclessError::Config(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_s3_endpoint_uses_path_style_requests() {
+        let (port, request) = capture_one_request();
+        let config = SoclessConfig::builder()
+            .s3_endpoint_url(format!("http://127.0.0.1:{}", port))
+            .build()
+            .unwrap();
+        let clients = SoclessClients::from_config(&test_aws_config(), &config).unwrap();
+
+        // only the request matters, not how the client parses the empty response
+        let _ = clients
+            .s3
+            .delete_object()
+            .bucket("socless-vault")
+            .key("reports/report.html")
+            .send()
+            .await;
+
+        let (request_line, host) = request.recv().unwrap();
+        assert!(
+            request_line.starts_with("DELETE /socless-vault/reports/report.html"),
+            "{}",
+            request_line
+        );
+        assert_eq!(host, format!("127.0.0.1:{}", port));
+    }
+
+    #[tokio::test]
+    async fn test_with_clients_only_applies_to_its_future() {
+        let clients =
+            SoclessClients::with_endpoint_url(&test_aws_config(), "http://127.0.0.1:9").unwrap();
+
+        let scoped = with_clients(clients, async { TASK_CLIENTS.try_with(|_| ()).is_ok() }).await;
+        assert!(scoped);
+        assert!(TASK_CLIENTS.try_with(|_| ()).is_err());
+    }
 }
@@ -0,0 +1,666 @@
+//! Settings for the SOCless tables, vault and AWS clients, loaded once per Lambda cold start.
+//!
+//! By default the config is read from the `SOCLESS_*` environment variables (see
+//! [`crate::constants`]) the first time it is needed. Call [`set_config`] before any other
+//! socless function to use a config built with [`SoclessConfig::builder`] or loaded with
+//! [`SoclessConfig::from_file`] instead.
+//!
+//! Every setting is validated together, so a misconfigured function reports all of its problems
+//! before any event is processed. Table names are only needed by the [`crate::DynamoStore`], which
+//! checks that the tables a function uses are configured (see [`SoclessConfig::validate_for`])
+//! before [`crate::bootstrap_integration`] or [`crate::create_events`] process an event.
+
+use crate::{
+    clients::AWS_ENDPOINT_URL,
+    constants::{
+        DEDUP_HASH_VERSION_ENV, DEDUP_TABLE_ENV, DYNAMODB_ENDPOINT_ENV, EVENTS_TABLE_ENV,
+        MESSAGE_RESPONSE_TABLE_ENV, RECORD_ATTEMPTS_ENV, REDACT_KEYS_ENV, REDACT_SAVED_RESULTS_ENV,
+        RESULTS_TABLE_ENV, RESULT_OFFLOAD_THRESHOLD_ENV, S3_ENDPOINT_ENV, SFN_ENDPOINT_ENV,
+        VAULT_ALLOW_PLAINTEXT_ENV, VAULT_BACKEND_ENV, VAULT_BUCKET_ENV, VAULT_KMS_KEY_ENV,
+    },
+    errors::{SoclessError, SoclessResult},
+    events::DedupHashVersion,
+    redaction::DEFAULT_REDACT_KEYS,
+    vault::DEFAULT_OFFLOAD_THRESHOLD_BYTES,
+};
+use hyper::Uri;
+use serde::{Deserialize, Serialize};
+use std::{env::var, fmt, fs, path::Path};
+use tokio::sync::OnceCell;
+
+/// Where vault files are stored, see [`crate::vault`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VaultBackendKind {
+    S3,
+    Local,
+}
+
+impl Default for VaultBackendKind {
+    fn default() -> Self {
+        VaultBackendKind::S3
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SoclessConfig {
+    pub results_table: Option<String>,
+    pub events_table: Option<String>,
+    pub dedup_table: Option<String>,
+    pub message_response_table: Option<String>,
+    /// S3 bucket, or directory for the local vault backend.
+    pub vault: Option<String>,
+    pub vault_backend: VaultBackendKind,
+    /// KMS key used to encrypt vault files, see [`crate::encryption`].
+    pub vault_kms_key_id: Option<String>,
+    /// With `vault_kms_key_id` set, still read vault files that aren't encrypted, e.g. files
+    /// saved before encryption was enabled. Off by default since those files aren't authenticated.
+    pub vault_allow_plaintext: bool,
+    /// Send every AWS request here instead of the regional endpoints (e.g. Localstack).
+    pub endpoint_url: Option<String>,
+    /// Send DynamoDB requests here instead of `endpoint_url` (e.g. DynamoDB Local).
+    pub dynamodb_endpoint_url: Option<String>,
+    /// Send Step Functions requests here instead of `endpoint_url`.
+    pub sfn_endpoint_url: Option<String>,
+    /// Send S3 requests here instead of `endpoint_url` (e.g. MinIO).
+    pub s3_endpoint_url: Option<String>,
+    pub dedup_hash_version: DedupHashVersion,
+    /// State results larger than this are offloaded to the vault.
+    pub result_offload_bytes: usize,
+    /// Keep a log of every attempt of each State (including Step Functions retries) in the
+    /// playbook's `attempts` map.
+    pub record_attempts: bool,
+    /// Patterns of key names whose values are scrubbed from logs, `*` matching any characters
+    /// (case-insensitive), see [`crate::redaction`].
+    pub redact_keys: Vec<String>,
+    /// Also scrub `redact_keys` from the State results saved to the results table. Later States
+    /// then see `[REDACTED]` in place of those values.
+    pub redact_saved_results: bool,
+}
+
+impl Default for SoclessConfig {
+    fn default() -> Self {
+        SoclessConfig {
+            results_table: None,
+            events_table: None,
+            dedup_table: None,
+            message_response_table: None,
+            vault: None,
+            vault_backend: VaultBackendKind::default(),
+            vault_kms_key_id: None,
+            vault_allow_plaintext: false,
+            endpoint_url: None,
+            dynamodb_endpoint_url: None,
+            sfn_endpoint_url: None,
+            s3_endpoint_url: None,
+            dedup_hash_version: DedupHashVersion::default(),
+            result_offload_bytes: DEFAULT_OFFLOAD_THRESHOLD_BYTES,
+            record_attempts: false,
+            redact_keys: DEFAULT_REDACT_KEYS
+                .iter()
+                .map(|key| key.to_string())
+                .collect(),
+            redact_saved_results: false,
+        }
+    }
+}
+
+/// The SOCless tables, for looking up their configured names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    Results,
+    Events,
+    Dedup,
+    MessageResponse,
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Table::Results => "results",
+            Table::Events => "events",
+            Table::Dedup => "dedup",
+            Table::MessageResponse => "message responses",
+        })
+    }
+}
+
+impl Table {
+    fn setting(self) -> (&'static str, &'static str) {
+        match self {
+            Table::Results => ("results_table", RESULTS_TABLE_ENV),
+            Table::Events => ("events_table", EVENTS_TABLE_ENV),
+            Table::Dedup => ("dedup_table", DEDUP_TABLE_ENV),
+            Table::MessageResponse => ("message_response_table", MESSAGE_RESPONSE_TABLE_ENV),
+        }
+    }
+}
+
+/// The AWS services called by socless, for looking up their endpoint overrides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwsService {
+    DynamoDb,
+    StepFunctions,
+    S3,
+    Kms,
+}
+
+impl SoclessConfig {
+    pub fn builder() -> SoclessConfigBuilder {
+        SoclessConfigBuilder::default()
+    }
+
+    /// Read and validate the config from the `SOCLESS_*` and `AWS_ENDPOINT_URL` environment
+    /// variables, unset variables keep their default.
+    pub fn from_env() -> SoclessResult<Self> {
+        Self::from_vars(|name| var(name).ok())
+    }
+
+    /// Read and validate a `.toml` or `.json` config file, using the field names of
+    /// [`SoclessConfig`]. Missing fields keep their default.
+    pub fn from_file(path: impl AsRef<Path>) -> SoclessResult<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|e| {
+            SoclessError::Config(format!("Unable to read {}: {}", path.display(), e))
+        })?;
+
+        let config: SoclessConfig = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| e.to_string()),
+            Some("json") => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            _ => Err("config files must be .toml or .json".to_owned()),
+        }
+        .map_err(|e| SoclessError::Config(format!("Invalid {}: {}", path.display(), e)))?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> SoclessResult<Self> {
+        let mut config = SoclessConfig {
+            results_table: var(RESULTS_TABLE_ENV),
+            events_table: var(EVENTS_TABLE_ENV),
+            dedup_table: var(DEDUP_TABLE_ENV),
+            message_response_table: var(MESSAGE_RESPONSE_TABLE_ENV),
+            vault: var(VAULT_BUCKET_ENV),
+            vault_kms_key_id: var(VAULT_KMS_KEY_ENV),
+            endpoint_url: var(AWS_ENDPOINT_URL),
+            dynamodb_endpoint_url: var(DYNAMODB_ENDPOINT_ENV),
+            sfn_endpoint_url: var(SFN_ENDPOINT_ENV),
+            s3_endpoint_url: var(S3_ENDPOINT_ENV),
+            ..Default::default()
+        };
+
+        let mut problems = vec![];
+        if let Some(backend) = var(VAULT_BACKEND_ENV) {
+            match backend.as_str() {
+                "s3" => config.vault_backend = VaultBackendKind::S3,
+                "local" => config.vault_backend = VaultBackendKind::Local,
+                other => problems.push(format!(
+                    "{} must be 's3' or 'local', found: {}",
+                    VAULT_BACKEND_ENV, other
+                )),
+            }
+        }
+        if let Some(version) = var(DEDUP_HASH_VERSION_ENV) {
+            match version.parse() {
+                Ok(version) => config.dedup_hash_version = version,
+                Err(_) => problems.push(format!(
+                    "{} must be 'legacy', 'v1' or 'v2', found: {}",
+                    DEDUP_HASH_VERSION_ENV, version
+                )),
+            }
+        }
+        if let Some(threshold) = var(RESULT_OFFLOAD_THRESHOLD_ENV) {
+            match threshold.parse() {
+                Ok(threshold) => config.result_offload_bytes = threshold,
+                Err(_) => problems.push(format!(
+                    "{} must be a number of bytes, found: {}",
+                    RESULT_OFFLOAD_THRESHOLD_ENV, threshold
+                )),
+            }
+        }
+        if let Some(patterns) = var(REDACT_KEYS_ENV) {
+            config.redact_keys = patterns
+                .split(',')
+                .map(str::trim)
+                .filter(|pattern| !pattern.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+        if let Some(redact_saved_results) = var(REDACT_SAVED_RESULTS_ENV) {
+            match redact_saved_results.parse() {
+                Ok(redact_saved_results) => config.redact_saved_results = redact_saved_results,
+                Err(_) => problems.push(format!(
+                    "{} must be 'true' or 'false', found: {}",
+                    REDACT_SAVED_RESULTS_ENV, redact_saved_results
+                )),
+            }
+        }
+        if let Some(allow_plaintext) = var(VAULT_ALLOW_PLAINTEXT_ENV) {
+            match allow_plaintext.parse() {
+                Ok(allow_plaintext) => config.vault_allow_plaintext = allow_plaintext,
+                Err(_) => problems.push(format!(
+                    "{} must be 'true' or 'false', found: {}",
+                    VAULT_ALLOW_PLAINTEXT_ENV, allow_plaintext
+                )),
+            }
+        }
+        if let Some(record_attempts) = var(RECORD_ATTEMPTS_ENV) {
+            match record_attempts.parse() {
+                Ok(record_attempts) => config.record_attempts = record_attempts,
+                Err(_) => problems.push(format!(
+                    "{} must be 'true' or 'false', found: {}",
+                    RECORD_ATTEMPTS_ENV, record_attempts
+                )),
+            }
+        }
+
+        problems.extend(config.problems());
+        config_result(config, problems)
+    }
+
+    /// Check every setting, returning a [`SoclessError::Config`] listing all the problems found.
+    pub fn validate(&self) -> SoclessResult<()> {
+        config_result((), self.problems())
+    }
+
+    /// Check every setting and that each of `tables` is configured, returning a
+    /// [`SoclessError::Config`] listing all the problems found.
+    /// ### Example
+    /// ```
+    /// # use socless::config::{SoclessConfig, Table};
+    /// let config = SoclessConfig::builder().results_table("socless_results").build().unwrap();
+    /// assert!(config.validate_for(&[Table::Results]).is_ok());
+    /// assert!(config.validate_for(&[Table::Results, Table::Events]).is_err());
+    /// ```
+    pub fn validate_for(&self, tables: &[Table]) -> SoclessResult<()> {
+        let mut problems = self.problems();
+        for table in tables {
+            if self.table(*table).is_err() {
+                let (setting, env_var) = table.setting();
+                problems.push(format!("{} is not configured, set {}", setting, env_var));
+            }
+        }
+        config_result((), problems)
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        let named_settings = [
+            ("results_table", &self.results_table),
+            ("events_table", &self.events_table),
+            ("dedup_table", &self.dedup_table),
+            ("message_response_table", &self.message_response_table),
+            ("vault", &self.vault),
+            ("vault_kms_key_id", &self.vault_kms_key_id),
+            ("endpoint_url", &self.endpoint_url),
+            ("dynamodb_endpoint_url", &self.dynamodb_endpoint_url),
+            ("sfn_endpoint_url", &self.sfn_endpoint_url),
+            ("s3_endpoint_url", &self.s3_endpoint_url),
+        ];
+        for (setting, value) in named_settings {
+            match value {
+                Some(value) if value.trim().is_empty() => {
+                    problems.push(format!("{} is set but empty", setting))
+                }
+                Some(url) if setting.ends_with("endpoint_url") => {
+                    if let Err(e) = url.parse::<Uri>() {
+                        problems.push(format!("{} {} is invalid: {}", setting, url, e));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if self.vault_backend == VaultBackendKind::Local && self.vault.is_none() {
+            problems.push(format!(
+                "the local vault backend needs a directory, set vault or {}",
+                VAULT_BUCKET_ENV
+            ));
+        }
+        if self.result_offload_bytes == 0 {
+            problems.push("result_offload_bytes must be greater than 0".to_owned());
+        }
+
+        problems
+    }
+
+    /// The configured name of `table`.
+    pub fn table(&self, table: Table) -> SoclessResult<&str> {
+        let name = match table {
+            Table::Results => &self.results_table,
+            Table::Events => &self.events_table,
+            Table::Dedup => &self.dedup_table,
+            Table::MessageResponse => &self.message_response_table,
+        };
+        name.as_deref().ok_or_else(|| {
+            let (setting, env_var) = table.setting();
+            SoclessError::Config(format!(
+                "{} is not configured, set {} or call set_config",
+                setting, env_var
+            ))
+        })
+    }
+
+    /// Where to send requests for `service`: its own endpoint, otherwise the shared
+    /// `endpoint_url`, otherwise `None` for the regional AWS endpoint.
+    pub fn endpoint_url_for(&self, service: AwsService) -> Option<&str> {
+        let service_endpoint = match service {
+            AwsService::DynamoDb => &self.dynamodb_endpoint_url,
+            AwsService::StepFunctions => &self.sfn_endpoint_url,
+            AwsService::S3 => &self.s3_endpoint_url,
+            AwsService::Kms => &None,
+        };
+        service_endpoint.as_deref().or(self.endpoint_url.as_deref())
+    }
+
+    /// The vault bucket or directory.
+    pub fn vault(&self) -> SoclessResult<&str> {
+        self.vault.as_deref().ok_or_else(|| {
+            SoclessError::Config(format!(
+                "vault is not configured, set {} or call set_config",
+                VAULT_BUCKET_ENV
+            ))
+        })
+    }
+}
+
+fn config_result<T>(value: T, problems: Vec<String>) -> SoclessResult<T> {
+    if problems.is_empty() {
+        Ok(value)
+    } else {
+        Err(SoclessError::Config(format!(
+            "invalid socless config: {}",
+            problems.join("; ")
+        )))
+    }
+}
+
+/// Builds a [`SoclessConfig`] in code, unset settings keep their default.
+/// ### Example
+/// ```
+/// # use socless::config::SoclessConfig;
+/// let config = SoclessConfig::builder()
+///     .results_table("socless_results")
+///     .vault("socless-vault-bucket")
+///     .build()
+///     .unwrap();
+/// assert_eq!(config.results_table.as_deref(), Some("socless_results"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SoclessConfigBuilder {
+    config: SoclessConfig,
+}
+
+impl SoclessConfigBuilder {
+    pub fn results_table(mut self, name: impl Into<String>) -> Self {
+        self.config.results_table = Some(name.into());
+        self
+    }
+
+    pub fn events_table(mut self, name: impl Into<String>) -> Self {
+        self.config.events_table = Some(name.into());
+        self
+    }
+
+    pub fn dedup_table(mut self, name: impl Into<String>) -> Self {
+        self.config.dedup_table = Some(name.into());
+        self
+    }
+
+    pub fn message_response_table(mut self, name: impl Into<String>) -> Self {
+        self.config.message_response_table = Some(name.into());
+        self
+    }
+
+    pub fn vault(mut self, bucket_or_dir: impl Into<String>) -> Self {
+        self.config.vault = Some(bucket_or_dir.into());
+        self
+    }
+
+    pub fn vault_backend(mut self, backend: VaultBackendKind) -> Self {
+        self.config.vault_backend = backend;
+        self
+    }
+
+    pub fn vault_kms_key_id(mut self, key_id: impl Into<String>) -> Self {
+        self.config.vault_kms_key_id = Some(key_id.into());
+        self
+    }
+
+    pub fn vault_allow_plaintext(mut self, allow_plaintext: bool) -> Self {
+        self.config.vault_allow_plaintext = allow_plaintext;
+        self
+    }
+
+    pub fn endpoint_url(mut self, url: impl Into<String>) -> Self {
+        self.config.endpoint_url = Some(url.into());
+        self
+    }
+
+    pub fn dynamodb_endpoint_url(mut self, url: impl Into<String>) -> Self {
+        self.config.dynamodb_endpoint_url = Some(url.into());
+        self
+    }
+
+    pub fn sfn_endpoint_url(mut self, url: impl Into<String>) -> Self {
+        self.config.sfn_endpoint_url = Some(url.into());
+        self
+    }
+
+    pub fn s3_endpoint_url(mut self, url: impl Into<String>) -> Self {
+        self.config.s3_endpoint_url = Some(url.into());
+        self
+    }
+
+    pub fn dedup_hash_version(mut self, version: DedupHashVersion) -> Self {
+        self.config.dedup_hash_version = version;
+        self
+    }
+
+    pub fn result_offload_bytes(mut self, bytes: usize) -> Self {
+        self.config.result_offload_bytes = bytes;
+        self
+    }
+
+    pub fn record_attempts(mut self, record_attempts: bool) -> Self {
+        self.config.record_attempts = record_attempts;
+        self
+    }
+
+    /// Replace the default [`DEFAULT_REDACT_KEYS`] patterns.
+    pub fn redact_keys(mut self, patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.config.redact_keys = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn redact_saved_results(mut self, redact_saved_results: bool) -> Self {
+        self.config.redact_saved_results = redact_saved_results;
+        self
+    }
+
+    pub fn build(self) -> SoclessResult<SoclessConfig> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+pub static SOCLESS_CONFIG: OnceCell<SoclessConfig> = OnceCell::const_new();
+pub async fn get_or_init_config() -> SoclessResult<&'static SoclessConfig> {
+    SOCLESS_CONFIG
+        .get_or_try_init(|| async { SoclessConfig::from_env() })
+        .await
+}
+
+/// Replace the config read from the environment with `config`, which is validated first.
+///
+/// Returns `Ok(false)` if a config was already initialized, in which case `config` is dropped.
+pub fn set_config(config: SoclessConfig) -> SoclessResult<bool> {
+    config.validate()?;
+    Ok(SOCLESS_CONFIG.set(config).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from_vars(vars: &[(&str, &str)]) -> SoclessResult<SoclessConfig> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        SoclessConfig::from_vars(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn test_config_from_env_vars() {
+        let config = from_vars(&[
+            (RESULTS_TABLE_ENV, "results"),
+            (VAULT_BUCKET_ENV, "/tmp/vault"),
+            (VAULT_BACKEND_ENV, "local"),
+            (DEDUP_HASH_VERSION_ENV, "legacy"),
+            (RESULT_OFFLOAD_THRESHOLD_ENV, "2048"),
+            (RECORD_ATTEMPTS_ENV, "true"),
+            (VAULT_ALLOW_PLAINTEXT_ENV, "true"),
+            (REDACT_KEYS_ENV, "password, *api_key*"),
+            (REDACT_SAVED_RESULTS_ENV, "true"),
+            (AWS_ENDPOINT_URL, "http://localhost:4566"),
+        ])
+        .unwrap();
+
+        assert_eq!(config.table(Table::Results).unwrap(), "results");
+        assert!(matches!(
+            config.table(Table::Events),
+            Err(SoclessError::Config(_))
+        ));
+        assert_eq!(config.vault().unwrap(), "/tmp/vault");
+        assert_eq!(config.vault_backend, VaultBackendKind::Local);
+        assert_eq!(config.dedup_hash_version, DedupHashVersion::Legacy);
+        assert_eq!(config.result_offload_bytes, 2048);
+        assert!(config.record_attempts);
+        assert!(config.vault_allow_plaintext);
+        assert_eq!(config.redact_keys, ["password", "*api_key*"]);
+        assert!(config.redact_saved_results);
+        assert_eq!(
+            config.endpoint_url.as_deref(),
+            Some("http://localhost:4566")
+        );
+
+        assert_eq!(from_vars(&[]).unwrap(), SoclessConfig::default());
+    }
+
+    #[test]
+    fn test_config_endpoint_overrides() {
+        let config = from_vars(&[
+            (AWS_ENDPOINT_URL, "http://localhost:4566"),
+            (DYNAMODB_ENDPOINT_ENV, "http://localhost:8000"),
+            (S3_ENDPOINT_ENV, "http://localhost:9000"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            config.endpoint_url_for(AwsService::DynamoDb),
+            Some("http://localhost:8000")
+        );
+        assert_eq!(
+            config.endpoint_url_for(AwsService::S3),
+            Some("http://localhost:9000")
+        );
+        assert_eq!(
+            config.endpoint_url_for(AwsService::StepFunctions),
+            Some("http://localhost:4566")
+        );
+        assert_eq!(
+            config.endpoint_url_for(AwsService::Kms),
+            Some("http://localhost:4566")
+        );
+
+        let no_shared_endpoint = from_vars(&[(SFN_ENDPOINT_ENV, "http://localhost:8083")]).unwrap();
+        assert_eq!(
+            no_shared_endpoint.endpoint_url_for(AwsService::DynamoDb),
+            None
+        );
+    }
+
+    #[test]
+    fn test_config_reports_every_problem() {
+        let error = from_vars(&[
+            (RESULTS_TABLE_ENV, ""),
+            (VAULT_BACKEND_ENV, "local"),
+            (DEDUP_HASH_VERSION_ENV, "v3"),
+            (RESULT_OFFLOAD_THRESHOLD_ENV, "lots"),
+            (RECORD_ATTEMPTS_ENV, "yes"),
+            (AWS_ENDPOINT_URL, "http://local host"),
+            (S3_ENDPOINT_ENV, "http://minio host"),
+        ])
+        .unwrap_err();
+
+        let message = error.to_string();
+        for problem in [
+            "results_table is set but empty",
+            "local vault backend needs a directory",
+            "v3",
+            RESULT_OFFLOAD_THRESHOLD_ENV,
+            RECORD_ATTEMPTS_ENV,
+            "endpoint_url http://local host",
+            "s3_endpoint_url http://minio host",
+        ] {
+            assert!(message.contains(problem), "{} missing {}", message, problem);
+        }
+    }
+
+    #[test]
+    fn test_config_validate_for_required_tables() {
+        let config = from_vars(&[(RESULTS_TABLE_ENV, "results")]).unwrap();
+        assert!(config.validate_for(&[Table::Results]).is_ok());
+
+        let message = config
+            .validate_for(&[Table::Results, Table::Events, Table::Dedup])
+            .unwrap_err()
+            .to_string();
+        assert!(!message.contains(RESULTS_TABLE_ENV), "{}", message);
+        assert!(message.contains(EVENTS_TABLE_ENV), "{}", message);
+        assert!(message.contains(DEDUP_TABLE_ENV), "{}", message);
+    }
+
+    #[test]
+    fn test_config_from_file() {
+        let dir = std::env::temp_dir().join(format!("socless-config-{}", crate::gen_id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let toml_path = dir.join("socless.toml");
+        fs::write(
+            &toml_path,
+            "results_table = \"results\"\nvault_backend = \"local\"\nvault = \"/tmp/vault\"\n",
+        )
+        .unwrap();
+        let json_path = dir.join("socless.json");
+        fs::write(
+            &json_path,
+            r#"{"results_table": "results", "vault_backend": "local", "vault": "/tmp/vault"}"#,
+        )
+        .unwrap();
+
+        let expected = SoclessConfig::builder()
+            .results_table("results")
+            .vault_backend(VaultBackendKind::Local)
+            .vault("/tmp/vault")
+            .build()
+            .unwrap();
+        assert_eq!(SoclessConfig::from_file(&toml_path).unwrap(), expected);
+        assert_eq!(SoclessConfig::from_file(&json_path).unwrap(), expected);
+
+        let typo_path = dir.join("typo.toml");
+        fs::write(&typo_path, "result_table = \"results\"\n").unwrap();
+        assert!(matches!(
+            SoclessConfig::from_file(&typo_path),
+            Err(SoclessError::Config(_))
+        ));
+
+        let invalid_path = dir.join("invalid.json");
+        fs::write(&invalid_path, r#"{"vault_backend": "local"}"#).unwrap();
+        assert!(matches!(
+            SoclessConfig::from_file(&invalid_path),
+            Err(SoclessError::Config(_))
+        ));
+    }
+}
@@ -1 +1,16 @@
 pub const RESULTS_TABLE_ENV: &str = "SOCLESS_RESULTS_TABLE";
+pub const EVENTS_TABLE_ENV: &str = "SOCLESS_EVENTS_TABLE";
+pub const DEDUP_TABLE_ENV: &str = "SOCLESS_DEDUP_TABLE";
+pub const MESSAGE_RESPONSE_TABLE_ENV: &str = "SOCLESS_MESSAGE_RESPONSE_TABLE";
+pub const DEDUP_HASH_VERSION_ENV: &str = "SOCLESS_DEDUP_HASH_VERSION";
+pub const VAULT_BUCKET_ENV: &str = "SOCLESS_VAULT";
+pub const VAULT_BACKEND_ENV: &str = "SOCLESS_VAULT_BACKEND";
+pub const RESULT_OFFLOAD_THRESHOLD_ENV: &str = "SOCLESS_RESULT_OFFLOAD_BYTES";
+pub const VAULT_KMS_KEY_ENV: &str = "SOCLESS_VAULT_KMS_KEY_ID";
+pub const VAULT_ALLOW_PLAINTEXT_ENV: &str = "SOCLESS_VAULT_ALLOW_PLAINTEXT";
+pub const DYNAMODB_ENDPOINT_ENV: &str = "SOCLESS_DYNAMODB_ENDPOINT";
+pub const SFN_ENDPOINT_ENV: &str = "SOCLESS_SFN_ENDPOINT";
+pub const S3_ENDPOINT_ENV: &str = "SOCLESS_S3_ENDPOINT";
+pub const RECORD_ATTEMPTS_ENV: &str = "SOCLESS_RECORD_ATTEMPTS";
+pub const REDACT_KEYS_ENV: &str = "SOCLESS_REDACT_KEYS";
+pub const REDACT_SAVED_RESULTS_ENV: &str = "SOCLESS_REDACT_SAVED_RESULTS";
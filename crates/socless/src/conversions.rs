@@ -0,0 +1,288 @@
+//! `!conversion` suffixes for parameter references, e.g. `vault:config.json!json`.
+//!
+//! Built-in conversions:
+//! - `json`: parse a string as json
+//! - `int`, `float`, `bool`: parse a string (or convert a number) to that type. `int` fails for
+//!   numbers that aren't whole or don't fit an i64 instead of rounding them, e.g. `3.9` and `"3.9"`
+//! - `base64`: base64 encode a string, or the raw bytes of the file for `vault:` references
+//! - `lines`: split a string into an array of lines
+//! - `csv`: parse a string with a header row into an array of maps
+//!
+//! More can be added with [`register_conversion`].
+
+use crate::errors::{SoclessError, SoclessResult};
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    sync::{RwLock, RwLockReadGuard},
+};
+use tokio::sync::OnceCell;
+
+/// Converts the resolved value of a parameter reference.
+pub type Conversion = Box<dyn Fn(Value) -> SoclessResult<Value> + Send + Sync>;
+
+pub static CONVERSIONS: OnceCell<RwLock<HashMap<String, Conversion>>> = OnceCell::const_new();
+
+pub async fn get_or_init_conversions() -> &'static RwLock<HashMap<String, Conversion>> {
+    CONVERSIONS
+        .get_or_init(|| async { RwLock::new(builtin_conversions()) })
+        .await
+}
+
+/// Add a conversion usable as `!name` in parameter references, replacing any existing
+/// conversion with the same name.
+/// ### Example
+/// ```
+/// # use serde_json::{json, Value};
+/// # use socless::conversions::{apply_conversion, register_conversion};
+/// # tokio_test::block_on(async {
+/// register_conversion("reverse", |value: Value| {
+///     Ok(Value::String(value.as_str().unwrap_or_default().chars().rev().collect()))
+/// })
+/// .await;
+///
+/// let reversed = apply_conversion(json!("socless"), "reverse").await.unwrap();
+/// assert_eq!(reversed, json!("sselcos"));
+/// # });
+/// ```
+pub async fn register_conversion<F>(name: &str, conversion: F)
+where
+    F: Fn(Value) -> SoclessResult<Value> + Send + Sync + 'static,
+{
+    get_or_init_conversions()
+        .await
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(name.to_owned(), Box::new(conversion));
+}
+
+/// Whether `name` is a registered conversion.
+pub async fn is_conversion(name: &str) -> bool {
+    read_conversions().await.contains_key(name)
+}
+
+/// Apply the conversion registered as `name` to `value`.
+pub async fn apply_conversion(value: Value, name: &str) -> SoclessResult<Value> {
+    let conversions = read_conversions().await;
+    let conversion = conversions
+        .get(name)
+        .ok_or_else(|| SoclessError::Resolution(format!("unknown conversion !{}", name)))?;
+    conversion(value)
+}
+
+async fn read_conversions() -> RwLockReadGuard<'static, HashMap<String, Conversion>> {
+    // conversions are only ever inserted whole, so ignore poisoning
+    get_or_init_conversions()
+        .await
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+fn builtin_conversions() -> HashMap<String, Conversion> {
+    let mut conversions: HashMap<String, Conversion> = HashMap::new();
+    conversions.insert("json".to_owned(), Box::new(to_json));
+    conversions.insert("int".to_owned(), Box::new(to_int));
+    conversions.insert("float".to_owned(), Box::new(to_float));
+    conversions.insert("bool".to_owned(), Box::new(to_bool));
+    conversions.insert("base64".to_owned(), Box::new(to_base64));
+    conversions.insert("lines".to_owned(), Box::new(to_lines));
+    conversions.insert("csv".to_owned(), Box::new(to_csv));
+    conversions
+}
+
+fn conversion_error(conversion: &str, value: &Value) -> SoclessError {
+    SoclessError::Resolution(format!("unable to apply !{} to {}", conversion, value))
+}
+
+fn as_str<'v>(conversion: &str, value: &'v Value) -> SoclessResult<&'v str> {
+    value
+        .as_str()
+        .ok_or_else(|| conversion_error(conversion, value))
+}
+
+/// Values that are already json (anything but a string) are returned as is.
+fn to_json(value: Value) -> SoclessResult<Value> {
+    match &value {
+        Value::String(string) => serde_json::from_str(string).map_err(|e| {
+            SoclessError::Resolution(format!("unable to apply !json, invalid json: {}", e))
+        }),
+        _ => Ok(value),
+    }
+}
+
+fn to_int(value: Value) -> SoclessResult<Value> {
+    let int = match &value {
+        Value::Number(number) => number
+            .as_i64()
+            .or_else(|| number.as_f64().and_then(whole_i64)),
+        Value::String(string) => {
+            let string = string.trim();
+            string
+                .parse::<i64>()
+                .ok()
+                .or_else(|| string.parse::<f64>().ok().and_then(whole_i64))
+        }
+        Value::Bool(boolean) => Some(*boolean as i64),
+        _ => None,
+    };
+    int.map(Value::from)
+        .ok_or_else(|| conversion_error("int", &value))
+}
+
+/// `float` as an i64 if it is a whole number in the i64 range, so `!int` never truncates or
+/// saturates.
+fn whole_i64(float: f64) -> Option<i64> {
+    // i64::MAX as f64 rounds up to 2^63, which is out of range
+    let in_range = float >= i64::MIN as f64 && float < i64::MAX as f64;
+    (float.fract() == 0.0 && in_range).then(|| float as i64)
+}
+
+fn to_float(value: Value) -> SoclessResult<Value> {
+    let float = match &value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => string.trim().parse::<f64>().ok(),
+        _ => None,
+    };
+    float
+        .and_then(serde_json::Number::from_f64)
+        .map(Value::Number)
+        .ok_or_else(|| conversion_error("float", &value))
+}
+
+fn to_bool(value: Value) -> SoclessResult<Value> {
+    let boolean = match &value {
+        Value::Bool(boolean) => Some(*boolean),
+        Value::Number(number) => number.as_f64().map(|float| float != 0.0),
+        Value::String(string) => match string.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => Some(true),
+            "false" | "no" | "0" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    };
+    boolean
+        .map(Value::Bool)
+        .ok_or_else(|| conversion_error("bool", &value))
+}
+
+fn to_base64(value: Value) -> SoclessResult<Value> {
+    Ok(Value::String(base64::encode(as_str("base64", &value)?)))
+}
+
+fn to_lines(value: Value) -> SoclessResult<Value> {
+    Ok(as_str("lines", &value)?
+        .lines()
+        .map(|line| Value::String(line.to_owned()))
+        .collect())
+}
+
+fn to_csv(value: Value) -> SoclessResult<Value> {
+    let mut reader = csv::Reader::from_reader(as_str("csv", &value)?.as_bytes());
+    let csv_error = |e: csv::Error| {
+        SoclessError::Resolution(format!("unable to apply !csv, invalid csv: {}", e))
+    };
+
+    let headers = reader.headers().map_err(csv_error)?.clone();
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+        let row: Map<String, Value> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(header, field)| (header.to_owned(), Value::String(field.to_owned())))
+            .collect();
+        rows.push(Value::Object(row));
+    }
+    Ok(Value::Array(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_builtin_conversions() {
+        let cases = [
+            (json!("{\"a\": [1, 2]}"), "json", json!({"a": [1, 2]})),
+            (
+                json!({"already": "json"}),
+                "json",
+                json!({"already": "json"}),
+            ),
+            (json!(" 42 "), "int", json!(42)),
+            (json!(4.0), "int", json!(4)),
+            (json!("-4.0"), "int", json!(-4)),
+            (json!("3.5"), "float", json!(3.5)),
+            (json!("Yes"), "bool", json!(true)),
+            (json!(0), "bool", json!(false)),
+            (json!("socless"), "base64", json!("c29jbGVzcw==")),
+            (
+                json!("one\ntwo\r\nthree"),
+                "lines",
+                json!(["one", "two", "three"]),
+            ),
+            (
+                json!("name,age\nSterling,35\nCheryl,17\n"),
+                "csv",
+                json!([{"name": "Sterling", "age": "35"}, {"name": "Cheryl", "age": "17"}]),
+            ),
+        ];
+
+        for (value, conversion, expected) in cases {
+            assert_eq!(
+                apply_conversion(value.clone(), conversion).await.unwrap(),
+                expected,
+                "{}!{}",
+                value,
+                conversion
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conversion_errors() {
+        let cases = [
+            (json!("not json"), "json"),
+            (json!("forty two"), "int"),
+            (json!(3.9), "int"),
+            (json!("3.9"), "int"),
+            (json!(1e19), "int"),
+            (json!("1e19"), "int"),
+            (json!(u64::MAX), "int"),
+            (json!("maybe"), "bool"),
+            (json!(["a"]), "lines"),
+            (json!("a"), "not_a_conversion"),
+        ];
+
+        for (value, conversion) in cases {
+            assert!(
+                matches!(
+                    apply_conversion(value, conversion).await,
+                    Err(SoclessError::Resolution(_))
+                ),
+                "!{} should fail",
+                conversion
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_conversion() {
+        assert!(!is_conversion("shout").await);
+
+        register_conversion("shout", |value: Value| {
+            Ok(Value::String(format!(
+                "{}!",
+                value.as_str().unwrap_or_default().to_uppercase()
+            )))
+        })
+        .await;
+
+        assert!(is_conversion("shout").await);
+        assert_eq!(
+            apply_conversion(json!("hello"), "shout").await.unwrap(),
+            json!("HELLO!")
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Deadline-aware cancellation for integration handlers.
+//!
+//! [`bootstrap_integration`] runs the handler with a [`CancellationToken`] that is cancelled
+//! [`DEADLINE_MARGIN`] before the Lambda deadline, leaving time to save the State's results or
+//! failure instead of being killed mid-write.
+//! # Example
+//! ```ignore
+//! async fn handler(params: Params) -> Result<Output, String> {
+//!     tokio::select! {
+//!         alerts = fetch_all_alerts(&params) => Ok(Output { alerts }),
+//!         _ = socless::cancellation_token().cancelled() => {
+//!             Err("stopped before the Lambda timeout".to_owned())
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! [`bootstrap_integration`]: crate::integrations::bootstrap_integration
+
+use std::{
+    future::Future,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio_util::sync::CancellationToken;
+
+/// How long before the Lambda deadline the handler's token is cancelled.
+pub const DEADLINE_MARGIN: Duration = Duration::from_secs(1);
+
+tokio::task_local! {
+    static INVOCATION_TOKEN: CancellationToken;
+}
+
+/// The cancellation token of the current invocation, or a token that is never cancelled outside
+/// of an integration handler.
+pub fn cancellation_token() -> CancellationToken {
+    INVOCATION_TOKEN
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| CancellationToken::new())
+}
+
+/// Time left before `deadline_ms` (milliseconds since the unix epoch, as in the Lambda
+/// `Context`), zero once it has passed.
+pub fn time_until(deadline_ms: u64) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Duration::from_millis(deadline_ms).saturating_sub(now)
+}
+
+/// Run `f` with a [`cancellation_token`] that is cancelled [`DEADLINE_MARGIN`] before
+/// `deadline_ms`. A `deadline_ms` of 0 (e.g. `Context::default()` in tests) is never cancelled.
+pub async fn with_deadline<F: Future>(deadline_ms: u64, f: F) -> F::Output {
+    let token = CancellationToken::new();
+    let timer = (deadline_ms > 0).then(|| {
+        let token = token.clone();
+        let cancel_in = time_until(deadline_ms).saturating_sub(DEADLINE_MARGIN);
+        tokio::spawn(async move {
+            tokio::time::sleep(cancel_in).await;
+            token.cancel();
+        })
+    });
+
+    let output = INVOCATION_TOKEN.scope(token, f).await;
+    if let Some(timer) = timer {
+        timer.abort();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::timeout;
+
+    fn deadline_in(duration: Duration) -> u64 {
+        let deadline = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() + duration;
+        deadline.as_millis() as u64
+    }
+
+    #[tokio::test]
+    async fn test_token_cancelled_before_deadline() {
+        let deadline_ms = deadline_in(DEADLINE_MARGIN + Duration::from_millis(50));
+
+        let cancelled = with_deadline(deadline_ms, async {
+            timeout(Duration::from_secs(2), cancellation_token().cancelled()).await
+        })
+        .await;
+
+        assert!(cancelled.is_ok());
+        assert!(time_until(deadline_ms) > Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_token_without_deadline_is_never_cancelled() {
+        let cancelled = with_deadline(0, async {
+            timeout(Duration::from_millis(50), cancellation_token().cancelled()).await
+        })
+        .await;
+        assert!(cancelled.is_err());
+
+        assert!(!cancellation_token().is_cancelled());
+        assert_eq!(
+            time_until(deadline_in(Duration::ZERO) - 1_000),
+            Duration::ZERO
+        );
+    }
+}
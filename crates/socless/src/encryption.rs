@@ -0,0 +1,507 @@
+//! Client-side envelope encryption of vault files.
+//!
+//! Each file is encrypted with AES-256-GCM under a fresh data key, and the data key is stored
+//! next to the ciphertext encrypted by a [`KeyProvider`] (KMS in production). Wrap any
+//! [`VaultBackend`] in an [`EncryptedVault`], or set `SOCLESS_VAULT_KMS_KEY_ID` to encrypt the
+//! default vault with that KMS key.
+//!
+//! The file id and content type are bound to the ciphertext as AES-GCM associated data, so an
+//! encrypted file can't be moved to another id or given another content type without failing to
+//! decrypt. Files without the envelope header are rejected, unless plaintext files written before
+//! encryption was enabled are allowed with [`EncryptedVault::allow_plaintext`]
+//! (`SOCLESS_VAULT_ALLOW_PLAINTEXT`).
+
+use crate::{
+    clients::get_or_init_kms,
+    errors::{SoclessError, SoclessResult},
+    vault::{VaultBackend, VaultContent, VaultFile},
+};
+use aes_gcm::{
+    aead::{Aead, NewAead, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use async_trait::async_trait;
+use aws_sdk_kms::{model::DataKeySpec, Blob};
+use aws_sdk_s3::ByteStream;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+const ENVELOPE_MAGIC: &[u8] = b"SOCLENC1";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// A data key, in plaintext to encrypt with and encrypted to store.
+pub struct DataKey {
+    pub key_id: String,
+    pub plaintext: Vec<u8>,
+    pub encrypted: Vec<u8>,
+}
+
+/// Creates and decrypts the data keys used to encrypt vault files.
+#[async_trait]
+pub trait KeyProvider: Send + Sync {
+    /// A new 256 bit data key.
+    async fn generate_data_key(&self) -> SoclessResult<DataKey>;
+
+    /// Decrypt a data key made by [`KeyProvider::generate_data_key`] with the key `key_id`.
+    async fn decrypt_data_key(&self, key_id: &str, encrypted_key: &[u8]) -> SoclessResult<Vec<u8>>;
+}
+
+/// A [`KeyProvider`] that encrypts data keys with a master key held in memory, for tests and
+/// local development.
+pub struct LocalKeyProvider {
+    key_id: String,
+    master_key: [u8; KEY_LEN],
+}
+
+impl LocalKeyProvider {
+    pub fn new(key_id: impl Into<String>, master_key: [u8; KEY_LEN]) -> Self {
+        LocalKeyProvider {
+            key_id: key_id.into(),
+            master_key,
+        }
+    }
+
+    /// A provider with a random master key, files it encrypts can't be read by another instance.
+    pub fn generate(key_id: impl Into<String>) -> Self {
+        let mut master_key = [0; KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut master_key);
+        Self::new(key_id, master_key)
+    }
+}
+
+#[async_trait]
+impl KeyProvider for LocalKeyProvider {
+    async fn generate_data_key(&self) -> SoclessResult<DataKey> {
+        let mut plaintext = vec![0; KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut plaintext);
+        let encrypted = seal(&self.master_key, &plaintext, self.key_id.as_bytes())?;
+
+        Ok(DataKey {
+            key_id: self.key_id.to_owned(),
+            plaintext,
+            encrypted,
+        })
+    }
+
+    async fn decrypt_data_key(&self, key_id: &str, encrypted_key: &[u8]) -> SoclessResult<Vec<u8>> {
+        if key_id != self.key_id {
+            return Err(SoclessError::Vault(format!(
+                "Data key was encrypted with key {}, not {}",
+                key_id, self.key_id
+            )));
+        }
+        open(&self.master_key, encrypted_key, key_id.as_bytes())
+    }
+}
+
+/// A [`KeyProvider`] using an AWS KMS key.
+#[derive(Debug, Clone)]
+pub struct KmsKeyProvider {
+    key_id: String,
+}
+
+impl KmsKeyProvider {
+    /// `key_id` is a KMS key id, ARN or alias.
+    pub fn new(key_id: impl Into<String>) -> Self {
+        KmsKeyProvider {
+            key_id: key_id.into(),
+        }
+    }
+}
+
+fn kms_error(action: &str, key_id: &str, e: impl std::fmt::Display) -> SoclessError {
+    SoclessError::Vault(format!(
+        "Unable to {} data key with KMS key {}: {}",
+        action, key_id, e
+    ))
+}
+
+#[async_trait]
+impl KeyProvider for KmsKeyProvider {
+    async fn generate_data_key(&self) -> SoclessResult<DataKey> {
+        let output = get_or_init_kms()
+            .await?
+            .generate_data_key()
+            .key_id(&self.key_id)
+            .key_spec(DataKeySpec::Aes256)
+            .send()
+            .await
+            .map_err(|e| kms_error("generate", &self.key_id, e))?;
+
+        match (output.plaintext, output.ciphertext_blob) {
+            (Some(plaintext), Some(encrypted)) => Ok(DataKey {
+                // the key ARN, so decrypting doesn't depend on an alias that may be re-pointed
+                key_id: output.key_id.unwrap_or_else(|| self.key_id.to_owned()),
+                plaintext: plaintext.into_inner(),
+                encrypted: encrypted.into_inner(),
+            }),
+            _ => Err(kms_error("generate", &self.key_id, "empty response")),
+        }
+    }
+
+    async fn decrypt_data_key(&self, key_id: &str, encrypted_key: &[u8]) -> SoclessResult<Vec<u8>> {
+        get_or_init_kms()
+            .await?
+            .decrypt()
+            .key_id(key_id)
+            .ciphertext_blob(Blob::new(encrypted_key))
+            .send()
+            .await
+            .map_err(|e| kms_error("decrypt", key_id, e))?
+            .plaintext
+            .map(Blob::into_inner)
+            .ok_or_else(|| kms_error("decrypt", key_id, "empty response"))
+    }
+}
+
+/// AES-256-GCM encrypt `plaintext` bound to `aad`, returning the random nonce followed by the
+/// ciphertext.
+fn seal(key: &[u8], plaintext: &[u8], aad: &[u8]) -> SoclessResult<Vec<u8>> {
+    let cipher = cipher(key)?;
+    let mut nonce = [0; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let ciphertext = cipher
+        .encrypt(
+            &Nonce::from(nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| SoclessError::Vault("Unable to encrypt vault file".to_owned()))?;
+    Ok([nonce.as_ref(), &ciphertext].concat())
+}
+
+/// Decrypt the output of [`seal`], with the same `aad`.
+fn open(key: &[u8], sealed: &[u8], aad: &[u8]) -> SoclessResult<Vec<u8>> {
+    let cipher = cipher(key)?;
+    if sealed.len() < NONCE_LEN {
+        return Err(SoclessError::Vault(
+            "Encrypted vault file is truncated".to_owned(),
+        ));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce: [u8; NONCE_LEN] = nonce.try_into().expect("split at NONCE_LEN");
+
+    cipher
+        .decrypt(
+            &Nonce::from(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| {
+            SoclessError::Vault(
+                "Unable to decrypt vault file, it was modified, moved or the wrong key was used"
+                    .to_owned(),
+            )
+        })
+}
+
+fn cipher(key: &[u8]) -> SoclessResult<Aes256Gcm> {
+    let key: [u8; KEY_LEN] = key.try_into().map_err(|_| {
+        SoclessError::Vault(format!(
+            "Data keys must be {} bytes, found {}",
+            KEY_LEN,
+            key.len()
+        ))
+    })?;
+    Ok(Aes256Gcm::new(&Key::from(key)))
+}
+
+#[derive(Serialize, Deserialize)]
+struct EnvelopeHeader {
+    key_id: String,
+    /// base64
+    encrypted_key: String,
+    /// Kept in the envelope rather than trusted from the backend, which may default it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+}
+
+/// The associated data of a vault file: its json quoted id followed by the envelope header, which
+/// holds its content type.
+fn associated_data(file_id: &str, header: &[u8]) -> SoclessResult<Vec<u8>> {
+    Ok([serde_json::to_vec(file_id)?.as_slice(), header].concat())
+}
+
+/// Whether `content` was written by [`encrypt_envelope`].
+pub fn is_envelope(content: &[u8]) -> bool {
+    content.starts_with(ENVELOPE_MAGIC)
+}
+
+/// Encrypt the vault file `file_id` under a new data key, as
+/// `SOCLENC1 | header length (u32 BE) | json header | nonce | ciphertext`.
+pub async fn encrypt_envelope(
+    key_provider: &dyn KeyProvider,
+    file_id: &str,
+    content: &VaultContent,
+) -> SoclessResult<Vec<u8>> {
+    let data_key = key_provider.generate_data_key().await?;
+    let header = serde_json::to_vec(&EnvelopeHeader {
+        key_id: data_key.key_id,
+        encrypted_key: base64::encode(&data_key.encrypted),
+        content_type: content.content_type.clone(),
+    })?;
+    let header_len = (header.len() as u32).to_be_bytes();
+    let aad = associated_data(file_id, &header)?;
+
+    Ok([
+        ENVELOPE_MAGIC,
+        &header_len,
+        &header,
+        &seal(&data_key.plaintext, &content.content, &aad)?,
+    ]
+    .concat())
+}
+
+/// Decrypt the output of [`encrypt_envelope`] for the same `file_id`.
+pub async fn decrypt_envelope(
+    key_provider: &dyn KeyProvider,
+    file_id: &str,
+    envelope: &[u8],
+) -> SoclessResult<VaultContent> {
+    let truncated = || SoclessError::Vault("Encrypted vault file is truncated".to_owned());
+
+    let rest = envelope
+        .strip_prefix(ENVELOPE_MAGIC)
+        .ok_or_else(|| SoclessError::Vault("Vault file is not encrypted".to_owned()))?;
+    if rest.len() < 4 {
+        return Err(truncated());
+    }
+    let (header_len, rest) = rest.split_at(4);
+    let header_len = u32::from_be_bytes(header_len.try_into().map_err(|_| truncated())?) as usize;
+    if rest.len() < header_len {
+        return Err(truncated());
+    }
+    let (header_bytes, sealed) = rest.split_at(header_len);
+
+    let header: EnvelopeHeader = serde_json::from_slice(header_bytes)?;
+    let encrypted_key = base64::decode(&header.encrypted_key)
+        .map_err(|e| SoclessError::Vault(format!("Invalid encrypted data key: {}", e)))?;
+    let data_key = key_provider
+        .decrypt_data_key(&header.key_id, &encrypted_key)
+        .await?;
+
+    let aad = associated_data(file_id, header_bytes)?;
+
+    Ok(VaultContent {
+        content: open(&data_key, sealed, &aad)?,
+        content_type: header.content_type,
+    })
+}
+
+/// A [`VaultBackend`] that encrypts files before writing them to another backend, and decrypts
+/// them when read. Content types and file ids are authenticated but not encrypted.
+///
+/// Decryption needs the whole file, so [`VaultBackend::get_stream`] reads it into memory.
+pub struct EncryptedVault {
+    inner: Box<dyn VaultBackend>,
+    key_provider: Box<dyn KeyProvider>,
+    allow_plaintext: bool,
+}
+
+impl EncryptedVault {
+    pub fn new(
+        inner: impl VaultBackend + 'static,
+        key_provider: impl KeyProvider + 'static,
+    ) -> Self {
+        Self::from_boxed(Box::new(inner), Box::new(key_provider))
+    }
+
+    pub fn from_boxed(inner: Box<dyn VaultBackend>, key_provider: Box<dyn KeyProvider>) -> Self {
+        EncryptedVault {
+            inner,
+            key_provider,
+            allow_plaintext: false,
+        }
+    }
+
+    /// Return files that aren't encrypted as is instead of failing, to read files saved before
+    /// encryption was enabled. Anyone able to write to the inner backend can then replace a file
+    /// with unauthenticated content.
+    pub fn allow_plaintext(mut self, allow_plaintext: bool) -> Self {
+        self.allow_plaintext = allow_plaintext;
+        self
+    }
+}
+
+#[async_trait]
+impl VaultBackend for EncryptedVault {
+    async fn put(
+        &self,
+        file_id: &str,
+        content: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> SoclessResult<()> {
+        let content = VaultContent {
+            content,
+            content_type: content_type.map(str::to_owned),
+        };
+        let envelope = encrypt_envelope(self.key_provider.as_ref(), file_id, &content).await?;
+        self.inner.put(file_id, envelope, content_type).await
+    }
+
+    async fn get(&self, file_id: &str) -> SoclessResult<VaultContent> {
+        let vault_content = self.inner.get(file_id).await?;
+        if is_envelope(&vault_content.content) {
+            decrypt_envelope(self.key_provider.as_ref(), file_id, &vault_content.content).await
+        } else if self.allow_plaintext {
+            Ok(vault_content)
+        } else {
+            Err(SoclessError::Vault(format!(
+                "Vault file {} is not encrypted, see EncryptedVault::allow_plaintext",
+                file_id
+            )))
+        }
+    }
+
+    async fn get_stream(&self, file_id: &str) -> SoclessResult<ByteStream> {
+        Ok(ByteStream::from(self.get(file_id).await?.content))
+    }
+
+    async fn list(&self, prefix: Option<&str>) -> SoclessResult<Vec<VaultFile>> {
+        self.inner.list(prefix).await
+    }
+
+    async fn delete(&self, file_id: &str) -> SoclessResult<()> {
+        self.inner.delete(file_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::{use_test_vault, LocalVault};
+
+    fn secret_content() -> VaultContent {
+        VaultContent {
+            content: b"top secret".to_vec(),
+            content_type: Some("text/plain".to_owned()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_envelope_roundtrip() {
+        let key_provider = LocalKeyProvider::generate("test-key");
+
+        let envelope = encrypt_envelope(&key_provider, "secret.txt", &secret_content())
+            .await
+            .unwrap();
+        assert!(is_envelope(&envelope));
+        assert!(!envelope
+            .windows(b"top secret".len())
+            .any(|window| window == b"top secret"));
+
+        let decrypted = decrypt_envelope(&key_provider, "secret.txt", &envelope)
+            .await
+            .unwrap();
+        assert_eq!(decrypted.content, b"top secret");
+        assert_eq!(decrypted.content_type.as_deref(), Some("text/plain"));
+    }
+
+    #[tokio::test]
+    async fn test_envelope_rejects_tampering_and_other_keys() {
+        let key_provider = LocalKeyProvider::generate("test-key");
+        let mut envelope = encrypt_envelope(&key_provider, "secret.txt", &secret_content())
+            .await
+            .unwrap();
+
+        let other_provider = LocalKeyProvider::generate("test-key");
+        assert!(matches!(
+            decrypt_envelope(&other_provider, "secret.txt", &envelope).await,
+            Err(SoclessError::Vault(_))
+        ));
+
+        // moved to another file id
+        assert!(matches!(
+            decrypt_envelope(&key_provider, "other.txt", &envelope).await,
+            Err(SoclessError::Vault(_))
+        ));
+
+        // content type changed in the header
+        let content_type_at = envelope
+            .windows(b"text/plain".len())
+            .position(|window| window == b"text/plain")
+            .unwrap();
+        let mut retyped = envelope.clone();
+        retyped[content_type_at..content_type_at + b"text/plain".len()]
+            .copy_from_slice(b"text/xhtml");
+        assert!(matches!(
+            decrypt_envelope(&key_provider, "secret.txt", &retyped).await,
+            Err(SoclessError::Vault(_))
+        ));
+
+        let last = envelope.len() - 1;
+        envelope[last] ^= 1;
+        assert!(matches!(
+            decrypt_envelope(&key_provider, "secret.txt", &envelope).await,
+            Err(SoclessError::Vault(_))
+        ));
+
+        assert!(matches!(
+            decrypt_envelope(&key_provider, "secret.txt", &envelope[..10]).await,
+            Err(SoclessError::Vault(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_vault() {
+        let dir = use_test_vault().await.join("encrypted-test");
+        let plain_vault = LocalVault::new(&dir);
+        let vault = EncryptedVault::new(
+            LocalVault::new(&dir),
+            LocalKeyProvider::generate("test-key"),
+        );
+
+        vault
+            .put("secret.txt", b"hunter2".to_vec(), Some("text/plain"))
+            .await
+            .unwrap();
+        vault
+            .put("public.txt", b"hello".to_vec(), Some("text/plain"))
+            .await
+            .unwrap();
+
+        let stored = plain_vault.get("secret.txt").await.unwrap();
+        assert!(is_envelope(&stored.content));
+        assert_eq!(stored.content_type.as_deref(), Some("text/plain"));
+
+        let decrypted = vault.get("secret.txt").await.unwrap();
+        assert_eq!(decrypted.content, b"hunter2");
+        assert_eq!(decrypted.content_type.as_deref(), Some("text/plain"));
+
+        // swapping encrypted files between ids is detected
+        plain_vault
+            .put("public.txt", stored.content, Some("text/plain"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            vault.get("public.txt").await,
+            Err(SoclessError::Vault(_))
+        ));
+
+        // files saved before encryption was enabled are only read when allowed
+        plain_vault
+            .put("legacy.txt", b"plaintext".to_vec(), None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            vault.get("legacy.txt").await,
+            Err(SoclessError::Vault(_))
+        ));
+
+        let legacy_vault = EncryptedVault::new(
+            LocalVault::new(&dir),
+            LocalKeyProvider::generate("test-key"),
+        )
+        .allow_plaintext(true);
+        assert_eq!(
+            legacy_vault.get("legacy.txt").await.unwrap().content,
+            b"plaintext"
+        );
+    }
+}
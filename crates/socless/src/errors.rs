@@ -1,34 +1,93 @@
+use crate::models::StateFailure;
 use thiserror::Error;
 // https://github.com/dtolnay/thiserror
 
-// #[derive(Error, Debug)]
-// pub enum SoclessError {
-//     #[error("key: {} not found in table: {}")]
-//     NotFoundError { key: String, table: String },
-//     // NotFoundError(#[from] io::Error),
-//     #[error("the data for key `{0}` is not available")]
-//     Redaction(String),
-//     #[error("invalid header (expected {expected:?}, found {found:?})")]
-//     InvalidHeader { expected: String, found: String },
-//     #[error("unknown data store error")]
-//     Unknown,
-// }
+pub type SoclessResult<T> = Result<T, SoclessError>;
 
+/// Every error that can be returned by the socless public API.
 #[derive(Error, Debug)]
 pub enum SoclessError {
-    #[error("key: {key} not found in table: {found}")]
-    NotFoundError {
-        key: String,
-        table: String,
-    },
-    DynamoError(String),
-    ConversionError(String),
+    /// Missing or invalid configuration, e.g. an unset `SOCLESS_*` environment variable.
+    #[error("configuration error: {0}")]
+    Config(String),
+    #[error("key: {key} not found in table: {table}")]
+    NotFound { key: String, table: String },
+    /// A table read or write failed.
+    #[error("storage error: {0}")]
+    Storage(String),
+    /// A table update was rejected for a document path the item doesn't have, e.g. a key inside a
+    /// map that doesn't exist.
+    #[error("invalid table update: {0}")]
+    InvalidUpdate(String),
+    /// A conditional table write was rejected because its condition wasn't met.
+    #[error("condition failed: {0}")]
+    ConditionFailed(String),
+    #[error("step functions error: {0}")]
+    StepFunctions(String),
+    #[error("vault error: {0}")]
+    Vault(String),
+    /// A parameter reference (`$.path`, `vault:id`) could not be resolved.
+    #[error("unable to resolve reference: {0}")]
+    Resolution(String),
+    /// The State's resolved parameters don't deserialize into the integration's `Input` type.
+    #[error("invalid integration parameters: {0}")]
+    InvalidParameters(String),
+    /// The integration handler returned an error.
+    #[error("{error_type}: {message}")]
+    Integration { error_type: String, message: String },
+    /// The integration handler returned something that can't be saved as a State's results.
+    #[error("invalid integration handler output: {0}")]
+    HandlerOutput(String),
+    /// The Lambda event, execution context or function arguments are malformed.
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    #[error("serialization error: {0}")]
+    Serialization(String),
+    /// A State failed and the failure was recorded in the playbook's `errors` map.
+    #[error("{0}")]
+    StateFailed(StateFailure),
 }
 
-// NotFoundError(#[from] io::Error),
-// #[error("the data for key `{0}` is not available")]
-// Redaction(String),
-// #[error("invalid header (expected {expected:?}, found {found:?})")]
-// InvalidHeader { expected: String, found: String },
-// #[error("unknown data store error")]
-// Unknown
+impl SoclessError {
+    /// Short name of the error, used as the `error_type` of a [`StateFailure`].
+    pub fn error_type(&self) -> String {
+        match self {
+            SoclessError::Config(_) => "Config",
+            SoclessError::NotFound { .. } => "NotFound",
+            SoclessError::Storage(_) => "Storage",
+            SoclessError::InvalidUpdate(_) => "InvalidUpdate",
+            SoclessError::ConditionFailed(_) => "ConditionFailed",
+            SoclessError::StepFunctions(_) => "StepFunctions",
+            SoclessError::Vault(_) => "Vault",
+            SoclessError::Resolution(_) => "Resolution",
+            SoclessError::InvalidParameters(_) => "InvalidParameters",
+            SoclessError::Integration { error_type, .. } => return error_type.to_owned(),
+            SoclessError::HandlerOutput(_) => "HandlerOutput",
+            SoclessError::InvalidInput(_) => "InvalidInput",
+            SoclessError::Serialization(_) => "Serialization",
+            SoclessError::StateFailed(failure) => return failure.error_type.to_owned(),
+        }
+        .to_owned()
+    }
+
+    /// The error message without the `error_type` prefix.
+    pub fn message(&self) -> String {
+        match self {
+            SoclessError::Integration { message, .. } => message.to_owned(),
+            SoclessError::StateFailed(failure) => failure.message.to_owned(),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for SoclessError {
+    fn from(e: serde_json::Error) -> Self {
+        SoclessError::Serialization(e.to_string())
+    }
+}
+
+impl From<serde_dynamo::Error> for SoclessError {
+    fn from(e: serde_dynamo::Error) -> Self {
+        SoclessError::Serialization(e.to_string())
+    }
+}
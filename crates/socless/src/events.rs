@@ -1,16 +1,20 @@
 // compare to https://github.com/twilio-labs/socless_python/blob/master/socless/events.py
 use crate::{
-    clients::get_or_init_sfn, constants::RESULTS_TABLE_ENV, gen_datetimenow, gen_id,
-    get_item_from_table, utils::put_item_in_table, EventTableItem, PlaybookArtifacts,
-    PlaybookInput, ResultsTableItem, SoclessEvent,
+    clients::get_or_init_sfn,
+    config::{get_or_init_config, Table},
+    constants::DEDUP_HASH_VERSION_ENV,
+    errors::{SoclessError, SoclessResult},
+    gen_datetimenow, gen_id,
+    store::{get_or_init_store, SoclessStore},
+    DedupTableItem, EventTableItem, PlaybookArtifacts, PlaybookInput, ResultsTableItem,
+    SoclessEvent,
 };
 use lambda_http::Context;
 use md5;
 use serde::{Deserialize, Serialize};
-use serde_dynamo::aws_sdk_dynamodb_0_4::from_item;
 use serde_json::{json, Value};
-use std::collections::HashMap;
-use std::env;
+use std::{collections::HashMap, str::FromStr};
+use tracing::{error, field::Empty, info, instrument, warn, Span};
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct SoclessEventBatch {
@@ -29,43 +33,66 @@ pub struct ExecutionStatus {
     pub message: Value,
 }
 
+#[instrument(skip_all, fields(
+    playbook = %event_batch.playbook,
+    event_type = %event_batch.event_type,
+    request_id = %lambda_context.request_id,
+))]
 pub async fn create_events(
     event_batch: SoclessEventBatch,
     lambda_context: lambda_http::Context,
-) -> Vec<ExecutionStatus> {
-    println!("lambda context: {:?}", lambda_context);
+) -> SoclessResult<Vec<ExecutionStatus>> {
+    let config = get_or_init_config().await?;
+    let store = get_or_init_store().await;
+    let mut required_tables = vec![Table::Events, Table::Results];
+    if event_batch
+        .dedup_keys
+        .as_ref()
+        .map_or(false, |keys| !keys.is_empty())
+    {
+        required_tables.push(Table::Dedup);
+    }
+    store.validate_config(config, &required_tables)?;
     let mut execution_statuses: Vec<ExecutionStatus> = vec![];
 
     let playbook = &event_batch.playbook.to_owned();
 
-    let formatted_events = setup_events(event_batch);
+    let formatted_events = setup_events(event_batch)?;
 
-    let playbook_arn = get_playbook_arn(playbook, &lambda_context);
+    let playbook_arn = get_playbook_arn(playbook, &lambda_context)?;
 
-    let events_table_name = std::env::var("SOCLESS_EVENTS_TABLE")
-        .expect("No env var found for SOCLESS_EVENTS_TABLE, please check serverless.yml");
+    let dedup_hash_version = config.dedup_hash_version;
 
     let mut events_subset: Vec<EventTableItem> = vec![];
     for event in formatted_events {
-        let deduplicated = deduplicate(event).await;
+        let deduplicated = deduplicate(event, store, dedup_hash_version).await?;
 
         let event_table_input = EventTableItem::from(deduplicated);
 
-        put_item_in_table(&events_table_name, &event_table_input)
-            .await
-            .expect("failed to store item");
+        store.put_event(&event_table_input).await?;
 
         events_subset.push(event_table_input);
     }
 
     for creation_event in events_subset {
-        execution_statuses.push(execute_playbook(creation_event, &playbook_arn).await);
+        // duplicates are attached to the open investigation instead of launching another playbook
+        if creation_event.is_duplicate {
+            execution_statuses.push(ExecutionStatus {
+                status: true,
+                message: json!({
+                    "investigation_id": creation_event.investigation_id,
+                    "is_duplicate": true,
+                }),
+            });
+            continue;
+        }
+        execution_statuses.push(execute_playbook(creation_event, &playbook_arn).await?);
     }
 
-    execution_statuses
+    Ok(execution_statuses)
 }
 
-fn setup_events(events_batch: SoclessEventBatch) -> Vec<SoclessEvent> {
+fn setup_events(events_batch: SoclessEventBatch) -> SoclessResult<Vec<SoclessEvent>> {
     let mut formatted_events = vec![];
 
     let created_at = events_batch.created_at.unwrap_or_else(gen_datetimenow);
@@ -81,7 +108,9 @@ fn setup_events(events_batch: SoclessEventBatch) -> Vec<SoclessEvent> {
             created_at: created_at.to_owned(),
             event_type: events_batch.event_type.to_owned(),
             playbook: events_batch.playbook.to_owned(),
-            details: serde_json::from_value(event_details).unwrap(),
+            details: serde_json::from_value(event_details).map_err(|e| {
+                SoclessError::InvalidInput(format!("event details must be a json object: {}", e))
+            })?,
             data_types: events_batch.data_types.clone().unwrap_or_default(),
             event_meta: events_batch.event_meta.clone().unwrap_or_default(),
             dedup_keys: events_batch.dedup_keys.clone().unwrap_or_default(),
@@ -90,82 +119,274 @@ fn setup_events(events_batch: SoclessEventBatch) -> Vec<SoclessEvent> {
         formatted_events.push(new_event);
     }
 
-    formatted_events
+    Ok(formatted_events)
 }
 
-fn build_dedup_hash(event: &SoclessEvent) -> String {
-    let dedup_kv_pairs: Vec<(String, Value)> = event
-        .details
-        .clone()
-        .into_iter()
-        .filter(|x| event.dedup_keys.clone().into_iter().any(|y| y == x.0))
-        .collect();
+/// Algorithm used to build the dedup hash that maps an event to its current investigation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupHashVersion {
+    /// md5 of the lowercased event type followed by the sorted dedup values formatted with
+    /// Python's `str()`, as built by socless_python. Use this to keep matching dedup tables written
+    /// by Python SOCless. Object values only match if their keys were sorted in the event, as
+    /// they're formatted in key order.
+    Legacy,
+    /// md5 of the lowercased event type and every `dedup_key=value` pair (values as canonical json),
+    /// prefixed with `v2-` so it never collides with a legacy hash.
+    V2,
+}
 
-    let mut sorted_dedup_values: Vec<String> = vec![];
-    for kv_pair in dedup_kv_pairs {
-        sorted_dedup_values.push(kv_pair.0);
+impl Default for DedupHashVersion {
+    fn default() -> Self {
+        DedupHashVersion::V2
     }
+}
 
-    sorted_dedup_values.sort_by_key(|a| a.to_lowercase());
+impl FromStr for DedupHashVersion {
+    type Err = SoclessError;
+
+    fn from_str(s: &str) -> SoclessResult<Self> {
+        match s.to_lowercase().as_str() {
+            "legacy" | "v1" => Ok(DedupHashVersion::Legacy),
+            "v2" => Ok(DedupHashVersion::V2),
+            other => Err(SoclessError::Config(format!(
+                "Unknown {}: {}, expected one of `legacy`, `v1`, `v2`",
+                DEDUP_HASH_VERSION_ENV, other
+            ))),
+        }
+    }
+}
 
-    let dedup_signature: String = format!(
-        "{}{}",
-        event.event_type.to_lowercase(),
-        sorted_dedup_values.join("")
-    );
+fn build_dedup_hash(event: &SoclessEvent, version: DedupHashVersion) -> String {
+    let dedup_signature = match version {
+        DedupHashVersion::Legacy => {
+            let mut sorted_dedup_values: Vec<String> = event
+                .dedup_keys
+                .iter()
+                .filter_map(|key| event.details.get(key))
+                .map(python_str)
+                .collect();
+            sorted_dedup_values.sort();
+
+            format!(
+                "{}{}",
+                event.event_type.to_lowercase(),
+                sorted_dedup_values.join("")
+            )
+        }
+        DedupHashVersion::V2 => {
+            let mut sorted_dedup_keys: Vec<&String> = event.dedup_keys.iter().collect();
+            sorted_dedup_keys.sort();
+            sorted_dedup_keys.dedup();
+
+            let dedup_pairs: Vec<String> = sorted_dedup_keys
+                .into_iter()
+                .map(|key| {
+                    format!(
+                        "{}={}",
+                        canonical_json(&Value::String(key.to_owned())),
+                        canonical_json(event.details.get(key).unwrap_or(&Value::Null))
+                    )
+                })
+                .collect();
+
+            format!(
+                "{}\n{}",
+                event.event_type.to_lowercase(),
+                dedup_pairs.join("\n")
+            )
+        }
+    };
 
     let dedup_hash = format!("{:x}", md5::compute(dedup_signature));
-    dedup_hash
+    match version {
+        DedupHashVersion::Legacy => dedup_hash,
+        DedupHashVersion::V2 => format!("v2-{}", dedup_hash),
+    }
 }
 
-async fn deduplicate(mut event: SoclessEvent) -> SoclessEvent {
-    // let cached_dedup_hash = dedup_hash.clone();
+/// Format a json value like Python's `str()` of the `json.loads`ed value, e.g. `True`, `1.0`,
+/// `{'a': 1}`.
+fn python_str(value: &Value) -> String {
+    match value {
+        Value::String(string_value) => string_value.to_owned(),
+        other => python_repr(other),
+    }
+}
 
-    let dedup_hash = build_dedup_hash(&event);
+fn python_repr(value: &Value) -> String {
+    match value {
+        Value::Null => "None".to_owned(),
+        Value::Bool(true) => "True".to_owned(),
+        Value::Bool(false) => "False".to_owned(),
+        Value::Number(number) => match number.as_f64() {
+            Some(float) if number.is_f64() => python_float_repr(float),
+            _ => number.to_string(),
+        },
+        Value::String(string_value) => python_string_repr(string_value),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(python_repr).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(map) => {
+            let pairs: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{}: {}", python_string_repr(key), python_repr(value)))
+                .collect();
+            format!("{{{}}}", pairs.join(", "))
+        }
+    }
+}
 
-    // get dedup_table item
-    ////! not implemented, TODO FIX
-    let dedup_mapping: HashMap<String, Value> = HashMap::new();
-    let possible_investigation_id = dedup_mapping.get("current_investigation_id");
+/// The shortest round-tripping digits, in scientific notation below 1e-4 and from 1e16 on.
+fn python_float_repr(float: f64) -> String {
+    let scientific = format!("{:e}", float);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let digits = mantissa.replace('.', "");
+
+    if (-4..16).contains(&exponent) {
+        let integer_len = exponent + 1;
+        if integer_len <= 0 {
+            format!("{}0.{}{}", sign, "0".repeat(-integer_len as usize), digits)
+        } else if digits.len() <= integer_len as usize {
+            format!(
+                "{}{:0<width$}.0",
+                sign,
+                digits,
+                width = integer_len as usize
+            )
+        } else {
+            let (integer, fraction) = digits.split_at(integer_len as usize);
+            format!("{}{}.{}", sign, integer, fraction)
+        }
+    } else {
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        format!(
+            "{}{}e{}{:02}",
+            sign,
+            mantissa,
+            exponent_sign,
+            exponent.abs()
+        )
+    }
+}
 
-    match possible_investigation_id {
-        None => println!(
-            "unmapped dedup_hash detected in dedup table: {}",
-            // json!({ "dedup_hash": cached_dedup_hash })
-            json!({ "dedup_hash": dedup_hash })
-        ),
-        Some(inv_id) => {
-            let current_investigation_id = inv_id.to_string();
+fn python_string_repr(string_value: &str) -> String {
+    let quote = if string_value.contains('\'') && !string_value.contains('"') {
+        '"'
+    } else {
+        '\''
+    };
+    let mut repr = String::from(quote);
+    for c in string_value.chars() {
+        match c {
+            '\\' => repr.push_str("\\\\"),
+            '\n' => repr.push_str("\\n"),
+            '\r' => repr.push_str("\\r"),
+            '\t' => repr.push_str("\\t"),
+            c if c == quote => {
+                repr.push('\\');
+                repr.push(c);
+            }
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                repr.push_str(&format!("\\x{:02x}", c as u32))
+            }
+            c => repr.push(c),
+        }
+    }
+    repr.push(quote);
+    repr
+}
+
+/// Serialize a json value with object keys sorted at every level, so equal values always hash the same.
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let pairs: Vec<String> = keys
+                .into_iter()
+                .map(|key| {
+                    format!(
+                        "{}:{}",
+                        canonical_json(&Value::String(key.to_owned())),
+                        canonical_json(&map[key])
+                    )
+                })
+                .collect();
+            format!("{{{}}}", pairs.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        scalar => scalar.to_string(),
+    }
+}
+
+/// Mark `event` as a duplicate if its dedup hash maps to an investigation that is still open,
+/// otherwise map the dedup hash to the event's new investigation in the dedup table.
+///
+/// Events without `dedup_keys` are never deduplicated.
+async fn deduplicate(
+    mut event: SoclessEvent,
+    store: &dyn SoclessStore,
+    dedup_hash_version: DedupHashVersion,
+) -> SoclessResult<SoclessEvent> {
+    if event.dedup_keys.is_empty() {
+        return Ok(event);
+    }
 
-            let events_table_name = std::env::var("SOCLESS_EVENTS_TABLE")
-                .expect("No env var found for SOCLESS_EVENTS_TABLE, please check serverless.yml");
+    let dedup_hash = build_dedup_hash(&event, dedup_hash_version);
 
-            let possible_existing_event =
-                get_item_from_table("id", &current_investigation_id, &events_table_name).await;
+    match store.get_dedup_mapping(&dedup_hash).await? {
+        None => info!(%dedup_hash, "unmapped dedup_hash detected in dedup table"),
+        Some(dedup_mapping) => {
+            let current_investigation_id = dedup_mapping.current_investigation_id;
 
-            match possible_existing_event {
-                Some(item) => {
-                    let existing_event: EventTableItem = from_item(item).unwrap();
+            match store.get_event(&current_investigation_id).await? {
+                Some(existing_event) => {
                     if existing_event.status_ != "closed" {
                         event.status_ = "closed".to_string();
                         event.investigation_id = existing_event.investigation_id;
                         event.is_duplicate = true;
+                        return Ok(event);
                     }
                 }
-                None => println!(
-                    "No existing investigation found for current_investigation_id: {}",
-                    &current_investigation_id
+                None => warn!(
+                    %current_investigation_id,
+                    "No existing investigation found for current_investigation_id"
                 ),
             }
         }
     };
 
-    event
+    store
+        .put_dedup_mapping(&DedupTableItem {
+            dedup_hash,
+            current_investigation_id: event.investigation_id.to_owned(),
+        })
+        .await?;
+
+    Ok(event)
 }
 
-async fn execute_playbook(creation_event: EventTableItem, playbook_arn: &str) -> ExecutionStatus {
+#[instrument(skip_all, fields(
+    investigation_id = %creation_event.investigation_id,
+    execution_id = Empty,
+))]
+async fn execute_playbook(
+    creation_event: EventTableItem,
+    playbook_arn: &str,
+) -> SoclessResult<ExecutionStatus> {
     let execution_id = gen_id();
     let investigation_id = creation_event.investigation_id.clone();
+    Span::current().record("execution_id", execution_id.as_str());
 
     // make playbook artifacts
     let playbook_artifacts = PlaybookArtifacts {
@@ -177,6 +398,8 @@ async fn execute_playbook(creation_event: EventTableItem, playbook_arn: &str) ->
         artifacts: playbook_artifacts,
         results: HashMap::new(),
         errors: HashMap::new(),
+        attempts: HashMap::new(),
+        metadata: HashMap::new(),
     };
 
     let results_table_input = ResultsTableItem {
@@ -186,12 +409,13 @@ async fn execute_playbook(creation_event: EventTableItem, playbook_arn: &str) ->
         results: playbook_input.clone(),
     };
 
-    put_item_in_table(&env::var(RESULTS_TABLE_ENV).unwrap(), &results_table_input)
+    get_or_init_store()
         .await
-        .expect("failed to store item");
+        .put_execution_results(&results_table_input)
+        .await?;
 
     let start_exec_response = get_or_init_sfn()
-        .await
+        .await?
         .start_execution()
         .name(&execution_id)
         .state_machine_arn(playbook_arn)
@@ -202,41 +426,68 @@ async fn execute_playbook(creation_event: EventTableItem, playbook_arn: &str) ->
         .send()
         .await;
 
-    return match start_exec_response {
-        Ok(start_exec_output) => ExecutionStatus {
-            status: true,
-            message: json!({
-                "execution_id" : start_exec_output.execution_arn,
-                "investigation_id" : investigation_id
-            }),
-        },
-        Err(error) => ExecutionStatus {
-            status: false,
-            message: json!({ "error": format!("Error during State Machine Start: {}", error) }),
-        },
-    };
+    Ok(match start_exec_response {
+        Ok(start_exec_output) => {
+            info!(%playbook_arn, "started playbook");
+            ExecutionStatus {
+                status: true,
+                message: json!({
+                    "execution_id" : start_exec_output.execution_arn,
+                    "investigation_id" : investigation_id
+                }),
+            }
+        }
+        Err(error) => {
+            error!(%playbook_arn, %error, "unable to start playbook");
+            ExecutionStatus {
+                status: false,
+                message: json!({ "error": format!("Error during State Machine Start: {}", error) }),
+            }
+        }
+    })
 }
 
-fn get_playbook_arn(playbook_name: &str, lambda_context: &Context) -> String {
+fn get_playbook_arn(playbook_name: &str, lambda_context: &Context) -> SoclessResult<String> {
     let lambda_arn_split = lambda_context
         .invoked_function_arn
         .split(':')
         .collect::<Vec<&str>>();
+    if lambda_arn_split.len() < 5 {
+        return Err(SoclessError::InvalidInput(format!(
+            "Unable to get region and account id from lambda function arn: {}",
+            lambda_context.invoked_function_arn
+        )));
+    }
     let region = lambda_arn_split[3];
     let account_id = lambda_arn_split[4];
 
-    format!(
+    Ok(format!(
         "arn:aws:states:{}:{}:stateMachine:{}",
         region, account_id, playbook_name
-    )
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::store::MemoryStore;
     // use lamedh_http::lambda::Config;
     use lambda_http::lambda_runtime::Config;
 
+    fn mock_dedup_event(investigation_id: &str) -> SoclessEvent {
+        SoclessEvent {
+            id: investigation_id.to_string(),
+            investigation_id: investigation_id.to_string(),
+            status_: "open".to_string(),
+            event_type: "SoclessDedupTest".to_string(),
+            playbook: "SoclessDedupTest".to_string(),
+            details: serde_json::from_value(json!({"user_id": "W12345", "text": "testing123"}))
+                .unwrap(),
+            dedup_keys: vec!["user_id".to_string()],
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_results_table_struct() {
         let mock_event_data = json!({
@@ -310,11 +561,21 @@ mod tests {
         };
 
         assert_eq!(
-            &get_playbook_arn("testing_playbook", &mock_context),
+            &get_playbook_arn("testing_playbook", &mock_context).unwrap(),
             "arn:aws:states:us-west-2:12345678901:stateMachine:testing_playbook"
         );
     }
 
+    #[test]
+    fn test_get_playbook_arn_invalid_arn() {
+        let mock_context = Context::default();
+
+        assert!(matches!(
+            get_playbook_arn("testing_playbook", &mock_context),
+            Err(SoclessError::InvalidInput(_))
+        ));
+    }
+
     #[test]
     fn test_build_dedup_hash() {
         let details: HashMap<String, Value> = serde_json::from_value(json!({
@@ -348,8 +609,208 @@ mod tests {
             dedup_keys: vec!["trigger_id".to_string()],
         };
 
-        let dedup_hash = build_dedup_hash(&mock_socless_event);
+        let dedup_hash = build_dedup_hash(&mock_socless_event, DedupHashVersion::Legacy);
+
+        // md5("soclessutilsintegrationtest123456789.123456789.b11d2434423456789")
+        assert_eq!("4efbd2b8f58b632ee2130f3471399baf".to_string(), dedup_hash);
+    }
+
+    #[test]
+    fn test_legacy_dedup_hash_formats_values_like_python() {
+        let mut event = mock_dedup_event("inv-1");
+        event.event_type = "SoclessUtilsIntegrationTest".to_string();
+        event.details = serde_json::from_value(json!({
+            "score": 1.0,
+            "active": true,
+            "user": {"name": "sterling"},
+            "ids": [1, "a"]
+        }))
+        .unwrap();
+        event.dedup_keys = vec![
+            "score".to_string(),
+            "active".to_string(),
+            "user".to_string(),
+            "ids".to_string(),
+        ];
+
+        // md5("soclessutilsintegrationtest1.0True[1, 'a']{'name': 'sterling'}")
+        assert_eq!(
+            build_dedup_hash(&event, DedupHashVersion::Legacy),
+            "f40ef393b0189d04e91f73d29e4d0cb6"
+        );
+
+        let reprs: Vec<String> = [
+            json!(1e16),
+            json!(1.5e-7),
+            json!(123.45),
+            json!(100.0),
+            json!(0.0001),
+            json!(-0.0),
+            json!(12345678901234567.0),
+            json!(null),
+            json!(["a\nb", "it's", false]),
+        ]
+        .iter()
+        .map(python_str)
+        .collect();
+        assert_eq!(
+            reprs,
+            [
+                "1e+16",
+                "1.5e-07",
+                "123.45",
+                "100.0",
+                "0.0001",
+                "-0.0",
+                "1.2345678901234568e+16",
+                "None",
+                "['a\\nb', \"it's\", False]"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deduplicate_maps_new_investigation() {
+        let store = MemoryStore::new();
+
+        let event = deduplicate(mock_dedup_event("inv-1"), &store, DedupHashVersion::V2)
+            .await
+            .unwrap();
+        assert!(!event.is_duplicate);
+        assert_eq!(event.status_, "open");
+
+        let mapping = store
+            .get_dedup_mapping(&build_dedup_hash(&event, DedupHashVersion::V2))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(mapping.current_investigation_id, "inv-1");
+    }
+
+    #[tokio::test]
+    async fn test_deduplicate_marks_duplicate_of_open_investigation() {
+        let store = MemoryStore::new();
+        let first = deduplicate(mock_dedup_event("inv-1"), &store, DedupHashVersion::V2)
+            .await
+            .unwrap();
+        store.put_event(&EventTableItem::from(first)).await.unwrap();
+
+        let second = deduplicate(mock_dedup_event("inv-2"), &store, DedupHashVersion::V2)
+            .await
+            .unwrap();
+        assert!(second.is_duplicate);
+        assert_eq!(second.status_, "closed");
+        assert_eq!(second.id, "inv-2");
+        assert_eq!(second.investigation_id, "inv-1");
+    }
+
+    #[tokio::test]
+    async fn test_deduplicate_remaps_after_investigation_closed() {
+        let store = MemoryStore::new();
+        let mut first = deduplicate(mock_dedup_event("inv-1"), &store, DedupHashVersion::V2)
+            .await
+            .unwrap();
+        first.status_ = "closed".to_string();
+        store.put_event(&EventTableItem::from(first)).await.unwrap();
+
+        let second = deduplicate(mock_dedup_event("inv-2"), &store, DedupHashVersion::V2)
+            .await
+            .unwrap();
+        assert!(!second.is_duplicate);
+        assert_eq!(second.investigation_id, "inv-2");
+
+        let mapping = store
+            .get_dedup_mapping(&build_dedup_hash(&second, DedupHashVersion::V2))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(mapping.current_investigation_id, "inv-2");
+    }
+
+    #[tokio::test]
+    async fn test_deduplicate_skips_events_without_dedup_keys() {
+        let store = MemoryStore::new();
+        let mut event = mock_dedup_event("inv-1");
+        event.dedup_keys = vec![];
 
-        assert_eq!("3dc424cb39725b818a72b796d7a64376".to_string(), dedup_hash);
+        let event = deduplicate(event, &store, DedupHashVersion::V2)
+            .await
+            .unwrap();
+        assert!(!event.is_duplicate);
+        assert!(store
+            .get_dedup_mapping(&build_dedup_hash(&event, DedupHashVersion::V2))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_build_dedup_hash_depends_on_values() {
+        let first = mock_dedup_event("inv-1");
+        let mut second = mock_dedup_event("inv-2");
+        second
+            .details
+            .insert("user_id".to_string(), json!("W99999"));
+
+        for version in [DedupHashVersion::Legacy, DedupHashVersion::V2] {
+            assert_ne!(
+                build_dedup_hash(&first, version),
+                build_dedup_hash(&second, version)
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_dedup_hash_ignores_non_dedup_keys() {
+        let first = mock_dedup_event("inv-1");
+        let mut second = mock_dedup_event("inv-2");
+        second
+            .details
+            .insert("text".to_string(), json!("something else"));
+
+        assert_eq!(
+            build_dedup_hash(&first, DedupHashVersion::V2),
+            build_dedup_hash(&second, DedupHashVersion::V2)
+        );
+    }
+
+    #[test]
+    fn test_build_dedup_hash_v2_canonicalizes_nested_values() {
+        let mut first = mock_dedup_event("inv-1");
+        first.dedup_keys = vec!["user".to_string(), "count".to_string()];
+        first.details = serde_json::from_value(
+            json!({"user": {"name": "sterling", "tags": ["a", "b"]}, "count": 3}),
+        )
+        .unwrap();
+
+        let mut second = mock_dedup_event("inv-2");
+        second.dedup_keys = vec!["count".to_string(), "user".to_string()];
+        second.details = serde_json::from_value(
+            json!({"count": 3, "user": {"tags": ["a", "b"], "name": "sterling"}}),
+        )
+        .unwrap();
+
+        let first_hash = build_dedup_hash(&first, DedupHashVersion::V2);
+        assert!(first_hash.starts_with("v2-"));
+        assert_eq!(first_hash, build_dedup_hash(&second, DedupHashVersion::V2));
+
+        second.details.insert("count".to_string(), json!("3"));
+        assert_ne!(first_hash, build_dedup_hash(&second, DedupHashVersion::V2));
+    }
+
+    #[test]
+    fn test_dedup_hash_version_from_str() {
+        assert_eq!(
+            "legacy".parse::<DedupHashVersion>().unwrap(),
+            DedupHashVersion::Legacy
+        );
+        assert_eq!(
+            "V2".parse::<DedupHashVersion>().unwrap(),
+            DedupHashVersion::V2
+        );
+        assert!(matches!(
+            "v3".parse::<DedupHashVersion>(),
+            Err(SoclessError::Config(_))
+        ));
     }
 }
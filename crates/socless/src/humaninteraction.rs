@@ -1,12 +1,15 @@
 use crate::{
-    constants::RESULTS_TABLE_ENV, gen_datetimenow, gen_id, get_item_from_table, get_or_init_dynamo,
-    get_or_init_sfn, integrations::save_state_results, utils::put_item_in_table,
-    ResponsesTableItem, ResultsTableItem, SoclessContext,
+    config::Table,
+    errors::{SoclessError, SoclessResult},
+    gen_datetimenow, gen_id, get_or_init_sfn,
+    integrations::save_state_results,
+    store::get_or_init_store,
+    ResponsesTableItem, SoclessContext,
 };
 use maplit::hashmap;
-use serde_dynamo::{from_item, to_attribute_value};
 use serde_json::{from_value, to_string, Value};
-use std::{collections::HashMap, env::var};
+use std::collections::HashMap;
+use tracing::{field::Empty, info, instrument, Span};
 
 /// Initialize the human interaction worfklow by saving the Human Interaction Task Token to SOCless Message Responses Table.
 ///
@@ -19,16 +22,22 @@ use std::{collections::HashMap, env::var};
 ///
 /// _RETURNS_: A `message_id` to embed in your message such that is returned as part of the human's response.
 /// It serves as a call_back ID to help SOCless match the users response to the right playbook execution
+#[instrument(skip_all, fields(
+    execution_id = ?execution_context.execution_id,
+    state_name = ?execution_context.state_name,
+))]
 pub async fn init_human_interaction<'a>(
     execution_context: SoclessContext,
     message_draft: &str,
     message_id: Option<String>,
-) -> String {
+) -> SoclessResult<String> {
     let resolved_msg_id = message_id.unwrap_or_else(gen_id);
 
-    let investigation_id: String =
-        from_value(execution_context.artifacts.unwrap()["event"]["investigation_id"].clone())
-            .unwrap();
+    let artifacts = execution_context
+        .artifacts
+        .ok_or_else(|| missing_context_field("artifacts"))?;
+    let investigation_id: String = from_value(artifacts["event"]["investigation_id"].clone())
+        .map_err(|_| missing_context_field("artifacts.event.investigation_id"))?;
 
     let response_table_item = ResponsesTableItem {
         investigation_id,
@@ -38,23 +47,25 @@ pub async fn init_human_interaction<'a>(
         fulfilled: false,
         execution_id: execution_context
             .execution_id
-            .expect("No execution id found in context"),
+            .ok_or_else(|| missing_context_field("execution_id"))?,
         receiver: execution_context
             .state_name
-            .expect("No `state_name` found in context"),
+            .ok_or_else(|| missing_context_field("state_name"))?,
         await_token: execution_context
             .task_token
-            .expect("No `await_token` found in context"),
+            .ok_or_else(|| missing_context_field("task_token"))?,
     };
 
-    put_item_in_table(
-        &var("SOCLESS_MESSAGE_RESPONSE_TABLE").expect("No env var set for response table"),
-        &response_table_item,
-    )
-    .await
-    .expect("failed to store item");
+    get_or_init_store()
+        .await
+        .put_message_response(&response_table_item)
+        .await?;
+
+    Ok(resolved_msg_id)
+}
 
-    resolved_msg_id
+fn missing_context_field(field: &str) -> SoclessError {
+    SoclessError::InvalidInput(format!("No `{}` found in execution context", field))
 }
 
 /// Completes a human interaction by returning the human's response to
@@ -63,39 +74,43 @@ pub async fn init_human_interaction<'a>(
 /// message_id (str): The ID in the human's response that identifies the interaction
 ///
 /// response_body (dict): The human's response
-pub async fn end_human_interaction(message_id: String, response_body: Value) {
-    let response_table_name =
-        var("SOCLESS_MESSAGE_RESPONSE_TABLE").expect("No env var set for response table");
-
-    let response_table_item = get_item_from_table("message_id", &message_id, &response_table_name)
-        .await
-        .expect("message_id not found in Response Table");
-
-    let response: ResponsesTableItem = from_item(response_table_item)
-        .expect("Unable to deserialize ResponseTableItem, malformed table item");
+#[instrument(skip(response_body), fields(execution_id = Empty, state_name = Empty))]
+pub async fn end_human_interaction(message_id: String, response_body: Value) -> SoclessResult<()> {
+    let store = get_or_init_store().await;
+
+    let response = store
+        .get_message_response(&message_id)
+        .await?
+        .ok_or_else(|| SoclessError::NotFound {
+            key: message_id.to_owned(),
+            table: Table::MessageResponse.to_string(),
+        })?;
+
+    Span::current()
+        .record("execution_id", response.execution_id.as_str())
+        .record("state_name", response.receiver.as_str());
 
     if response.fulfilled {
-        panic!(
+        return Err(SoclessError::InvalidInput(format!(
             "Message ID {} for end_human_interaction already used",
             message_id
-        )
+        )));
     }
 
-    let results_item = get_item_from_table(
-        &var(RESULTS_TABLE_ENV).unwrap(),
-        "execution_id",
-        &response.execution_id,
-    )
-    .await
-    .expect("execution_id not found in Results Table");
-
-    let results_table_item: ResultsTableItem = from_item(results_item)
-        .expect("Unable to deserialize to ResultsTableItem, malformed table item");
+    let results_table_item = store
+        .get_execution_results(&response.execution_id)
+        .await?
+        .ok_or_else(|| SoclessError::NotFound {
+            key: response.execution_id.to_owned(),
+            table: Table::Results.to_string(),
+        })?;
 
     let mut execution_results = results_table_item.results;
 
-    let response_body_as_hashmap: HashMap<String, Value> =
-        from_value(response_body.clone()).expect("response_body not a <String, Value> type");
+    let response_body_as_hashmap: HashMap<String, Value> = from_value(response_body.clone())
+        .map_err(|_| {
+            SoclessError::InvalidInput("response_body not a <String, Value> type".to_owned())
+        })?;
 
     execution_results.results = hashmap! {
         response.receiver.to_owned() => response_body.to_owned(),
@@ -108,39 +123,23 @@ pub async fn end_human_interaction(message_id: String, response_body: Value) {
         &response.execution_id,
         &response_body,
         None,
+        None,
     )
-    .await;
+    .await?;
 
     get_or_init_sfn()
-        .await
+        .await?
         .send_task_success()
         .task_token(response.await_token)
-        .output(
-            to_string(&execution_results)
-                .expect("Unable to convert PlaybookInput `execution_results` to json string"),
-        )
+        .output(to_string(&execution_results)?)
         .send()
         .await
-        .expect("step_functions.send_task_success failed");
+        .map_err(|e| {
+            SoclessError::StepFunctions(format!("step_functions.send_task_success failed: {}", e))
+        })?;
+    info!("resumed playbook with the human's response");
 
-    get_or_init_dynamo()
-        .await
-        .update_item()
-        .table_name(response_table_name)
-        .key("message_id", to_attribute_value(message_id).unwrap())
-        .update_expression(
-            "SET fulfilled = :fulfilled, response_payload = :response_payload".to_string(),
-        )
-        .expression_attribute_values(
-            ":fulfilled",
-            to_attribute_value(true).expect("Error converting to ExpressionAttributeValue"),
-        )
-        .expression_attribute_values(
-            ":response_payload",
-            to_attribute_value(response_body)
-                .expect("Error converting to ExpressionAttributeValue"),
-        )
-        .send()
+    store
+        .fulfill_message_response(&message_id, &response_body)
         .await
-        .expect("Unable to save result to Results Table");
 }
@@ -0,0 +1,742 @@
+//! JSONPath evaluation for `$.` references in State parameters.
+//!
+//! Supported syntax:
+//! - child names: `$.details.name`, `$['details']["name with.dots"]`
+//! - array indexes, negative indexes and slices: `$.items[0]`, `$.items[-1]`, `$.items[1:3]`, `$.items[::2]`
+//! - wildcards: `$.items[*]`, `$.details.*`
+//! - recursive descent: `$..pin`, `$..[0]`
+//! - unions: `$.items[0,2]`, `$.details['firstname','lastname']`
+//! - filters: `$.items[?(@.pin)]`, `$.users[?(@.age >= 18 && @.name != 'Archer')]`
+//!
+//! A path made only of names and indexes is *singular* and resolves to a single value,
+//! every other path resolves to an array of all matches.
+
+use crate::errors::{SoclessError, SoclessResult};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// A parsed JSONPath expression.
+/// ### Example
+/// ```
+/// # use serde_json::json;
+/// # use socless::jsonpath::JsonPath;
+/// let root = json!({"items": ["camera", {"pin": 1234}, {"pin": 5678}]});
+///
+/// let singular = JsonPath::parse("$.items[1].pin").unwrap();
+/// assert_eq!(singular.select(&root), vec![&json!(1234)]);
+///
+/// let pins = JsonPath::parse("$..pin").unwrap();
+/// assert_eq!(pins.select(&root), vec![&json!(1234), &json!(5678)]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Segment {
+    descendant: bool,
+    selectors: Vec<Selector>,
+    /// The text of the segment in the original path, used in error messages.
+    source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Name(String),
+    Index(i64),
+    Wildcard,
+    Slice {
+        start: Option<i64>,
+        end: Option<i64>,
+        step: Option<i64>,
+    },
+    Filter(Filter),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Exists(Operand),
+    Compare(Operand, Comparison, Operand),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    /// A path relative to the element being filtered, `@.name`
+    Current(JsonPath),
+    /// A path relative to the root object, `$.name`
+    Root(JsonPath),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl JsonPath {
+    /// Parse a JSONPath expression, which must start with `$`.
+    pub fn parse(path: &str) -> SoclessResult<JsonPath> {
+        let mut parser = Parser::new(path);
+        parser.expect('$')?;
+        let json_path = parser.path(false)?;
+        if !parser.at_end() {
+            return Err(parser.error("unexpected character"));
+        }
+        Ok(json_path)
+    }
+
+    /// Whether the path can only ever match a single value (it only uses names and indexes).
+    pub fn is_singular(&self) -> bool {
+        self.segments.iter().all(Segment::is_singular)
+    }
+
+    /// Every value matched by the path, in document order.
+    pub fn select<'v>(&self, root: &'v Value) -> Vec<&'v Value> {
+        self.select_from(root, root)
+    }
+
+    pub(crate) fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    fn select_from<'v>(&self, node: &'v Value, root: &Value) -> Vec<&'v Value> {
+        self.segments.iter().fold(vec![node], |nodes, segment| {
+            nodes
+                .into_iter()
+                .flat_map(|node| segment.select(node, root))
+                .collect()
+        })
+    }
+}
+
+impl Segment {
+    fn is_singular(&self) -> bool {
+        !self.descendant
+            && matches!(
+                self.selectors.as_slice(),
+                [Selector::Name(_)] | [Selector::Index(_)]
+            )
+    }
+
+    /// The segment as written in the path, without the leading dots.
+    pub(crate) fn key(&self) -> &str {
+        self.source.trim_start_matches('.')
+    }
+
+    /// The values selected by this segment from `node`, where `root` is the root of the document.
+    pub(crate) fn select<'v>(&self, node: &'v Value, root: &Value) -> Vec<&'v Value> {
+        let mut candidates = vec![node];
+        if self.descendant {
+            collect_descendants(node, &mut candidates);
+        }
+
+        let mut selected = vec![];
+        for candidate in candidates {
+            for selector in &self.selectors {
+                selector.select(candidate, root, &mut selected);
+            }
+        }
+        selected
+    }
+}
+
+impl Selector {
+    fn select<'v>(&self, node: &'v Value, root: &Value, selected: &mut Vec<&'v Value>) {
+        match self {
+            Selector::Name(name) => selected.extend(node.as_object().and_then(|m| m.get(name))),
+            Selector::Index(index) => {
+                if let Some(array) = node.as_array() {
+                    selected.extend(normalize_index(*index, array.len()).map(|i| &array[i]));
+                }
+            }
+            Selector::Wildcard => selected.extend(children(node)),
+            Selector::Slice { start, end, step } => {
+                if let Some(array) = node.as_array() {
+                    selected
+                        .extend(slice_indexes(*start, *end, *step, array.len()).map(|i| &array[i]));
+                }
+            }
+            Selector::Filter(filter) => {
+                selected.extend(children(node).filter(|child| filter.test(child, root)))
+            }
+        }
+    }
+}
+
+impl Filter {
+    fn test(&self, current: &Value, root: &Value) -> bool {
+        match self {
+            Filter::Exists(operand) => operand.evaluate(current, root).is_some(),
+            Filter::Compare(left, comparison, right) => {
+                comparison.compare(left.evaluate(current, root), right.evaluate(current, root))
+            }
+            Filter::Not(filter) => !filter.test(current, root),
+            Filter::And(left, right) => left.test(current, root) && right.test(current, root),
+            Filter::Or(left, right) => left.test(current, root) || right.test(current, root),
+        }
+    }
+}
+
+impl Operand {
+    fn evaluate<'v>(&'v self, current: &'v Value, root: &'v Value) -> Option<&'v Value> {
+        match self {
+            Operand::Current(path) => path.select_from(current, root).into_iter().next(),
+            Operand::Root(path) => path.select_from(root, root).into_iter().next(),
+            Operand::Literal(value) => Some(value),
+        }
+    }
+}
+
+impl Comparison {
+    fn compare(self, left: Option<&Value>, right: Option<&Value>) -> bool {
+        match self {
+            Comparison::Eq => values_equal(left, right),
+            Comparison::Ne => !values_equal(left, right),
+            _ => {
+                let ordering = match (left, right) {
+                    (Some(Value::Number(l)), Some(Value::Number(r))) => {
+                        l.as_f64().partial_cmp(&r.as_f64())
+                    }
+                    (Some(Value::String(l)), Some(Value::String(r))) => Some(l.cmp(r)),
+                    _ => None,
+                };
+                match ordering {
+                    Some(ordering) => match self {
+                        Comparison::Lt => ordering == Ordering::Less,
+                        Comparison::Le => ordering != Ordering::Greater,
+                        Comparison::Gt => ordering == Ordering::Greater,
+                        _ => ordering != Ordering::Less,
+                    },
+                    None => false,
+                }
+            }
+        }
+    }
+}
+
+/// `1` and `1.0` are equal, otherwise the usual json equality.
+fn values_equal(left: Option<&Value>, right: Option<&Value>) -> bool {
+    match (left, right) {
+        (Some(Value::Number(l)), Some(Value::Number(r))) => l.as_f64() == r.as_f64(),
+        (l, r) => l == r,
+    }
+}
+
+fn children(node: &Value) -> Box<dyn Iterator<Item = &Value> + '_> {
+    match node {
+        Value::Array(array) => Box::new(array.iter()),
+        Value::Object(map) => Box::new(map.values()),
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+fn collect_descendants<'v>(node: &'v Value, descendants: &mut Vec<&'v Value>) {
+    for child in children(node) {
+        descendants.push(child);
+        collect_descendants(child, descendants);
+    }
+}
+
+fn normalize_index(index: i64, len: usize) -> Option<usize> {
+    let len = len as i64;
+    let index = if index < 0 { len + index } else { index };
+    (0..len).contains(&index).then(|| index as usize)
+}
+
+/// Array indexes selected by a python-style `[start:end:step]` slice.
+fn slice_indexes(
+    start: Option<i64>,
+    end: Option<i64>,
+    step: Option<i64>,
+    len: usize,
+) -> impl Iterator<Item = usize> {
+    let len = len as i64;
+    let step = step.unwrap_or(1);
+    let bound = |index: i64, lower: i64, upper: i64| {
+        let index = if index < 0 { len + index } else { index };
+        index.clamp(lower, upper)
+    };
+
+    let mut indexes = vec![];
+    if step > 0 {
+        let start = start.map_or(0, |i| bound(i, 0, len));
+        let end = end.map_or(len, |i| bound(i, 0, len));
+        let mut i = start;
+        while i < end {
+            indexes.push(i as usize);
+            match i.checked_add(step) {
+                Some(next) => i = next,
+                None => break,
+            }
+        }
+    } else if step < 0 {
+        let start = start.map_or(len - 1, |i| bound(i, -1, len - 1));
+        let end = end.map_or(-1, |i| bound(i, -1, len - 1));
+        let mut i = start;
+        while i > end {
+            indexes.push(i as usize);
+            match i.checked_add(step) {
+                Some(next) => i = next,
+                None => break,
+            }
+        }
+    }
+    indexes.into_iter()
+}
+
+struct Parser<'a> {
+    path: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(path: &'a str) -> Self {
+        Parser {
+            path,
+            chars: path.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn error(&self, reason: &str) -> SoclessError {
+        SoclessError::Resolution(format!(
+            "invalid JsonPath {}: {} at position {}",
+            self.path, reason, self.pos
+        ))
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_str(&mut self, expected: &str) -> bool {
+        let matches = expected
+            .chars()
+            .enumerate()
+            .all(|(offset, c)| self.peek_at(offset) == Some(c));
+        if matches {
+            self.pos += expected.chars().count();
+        }
+        matches
+    }
+
+    fn expect(&mut self, expected: char) -> SoclessResult<()> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn source_since(&self, start: usize) -> String {
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Parse segments until a character that can't start one. Inside a filter, dot names also
+    /// end at whitespace and operators.
+    fn path(&mut self, in_filter: bool) -> SoclessResult<JsonPath> {
+        let mut segments = vec![];
+        loop {
+            let start = self.pos;
+            let (descendant, selectors) = if self.eat_str("..") {
+                let selectors = match self.peek() {
+                    Some('[') => self.bracket()?,
+                    _ => vec![self.dot_selector(in_filter)?],
+                };
+                (true, selectors)
+            } else if self.eat('.') {
+                (false, vec![self.dot_selector(in_filter)?])
+            } else if self.peek() == Some('[') {
+                (false, self.bracket()?)
+            } else {
+                break;
+            };
+            segments.push(Segment {
+                descendant,
+                selectors,
+                source: self.source_since(start),
+            });
+        }
+        Ok(JsonPath { segments })
+    }
+
+    fn dot_selector(&mut self, in_filter: bool) -> SoclessResult<Selector> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            let ends_name =
+                c == '.' || c == '[' || (in_filter && (c.is_whitespace() || "=!<>&|)".contains(c)));
+            if ends_name {
+                break;
+            }
+            self.pos += 1;
+        }
+        match self.source_since(start).as_str() {
+            "" => Err(self.error("expected a name")),
+            "*" => Ok(Selector::Wildcard),
+            name => Ok(Selector::Name(name.to_owned())),
+        }
+    }
+
+    fn bracket(&mut self) -> SoclessResult<Vec<Selector>> {
+        self.expect('[')?;
+        let mut selectors = vec![];
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.bracket_selector()?);
+            self.skip_whitespace();
+            if self.eat(']') {
+                return Ok(selectors);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn bracket_selector(&mut self) -> SoclessResult<Selector> {
+        match self.peek() {
+            Some('\'') | Some('"') => Ok(Selector::Name(self.quoted_string()?)),
+            Some('*') => {
+                self.pos += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some('?') => {
+                self.pos += 1;
+                self.skip_whitespace();
+                self.expect('(')?;
+                let filter = self.filter_or()?;
+                self.skip_whitespace();
+                self.expect(')')?;
+                Ok(Selector::Filter(filter))
+            }
+            _ => {
+                let start = self.integer()?;
+                self.skip_whitespace();
+                if !self.eat(':') {
+                    return start
+                        .map(Selector::Index)
+                        .ok_or_else(|| self.error("expected a selector"));
+                }
+                self.skip_whitespace();
+                let end = self.integer()?;
+                self.skip_whitespace();
+                let step = if self.eat(':') {
+                    self.skip_whitespace();
+                    self.integer()?
+                } else {
+                    None
+                };
+                Ok(Selector::Slice { start, end, step })
+            }
+        }
+    }
+
+    fn integer(&mut self) -> SoclessResult<Option<i64>> {
+        let start = self.pos;
+        self.eat('-');
+        while self.peek().map_or(false, |c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        match self.source_since(start).as_str() {
+            "" => Ok(None),
+            digits => digits
+                .parse()
+                .map(Some)
+                .map_err(|_| self.error("expected an integer")),
+        }
+    }
+
+    fn quoted_string(&mut self) -> SoclessResult<String> {
+        let quote = self.peek().ok_or_else(|| self.error("expected a quote"))?;
+        self.pos += 1;
+        let mut string = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    string.push(escaped);
+                }
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(string);
+                }
+                Some(c) => string.push(c),
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn filter_or(&mut self) -> SoclessResult<Filter> {
+        let mut filter = self.filter_and()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat_str("||") {
+                return Ok(filter);
+            }
+            filter = Filter::Or(Box::new(filter), Box::new(self.filter_and()?));
+        }
+    }
+
+    fn filter_and(&mut self) -> SoclessResult<Filter> {
+        let mut filter = self.filter_unary()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat_str("&&") {
+                return Ok(filter);
+            }
+            filter = Filter::And(Box::new(filter), Box::new(self.filter_unary()?));
+        }
+    }
+
+    fn filter_unary(&mut self) -> SoclessResult<Filter> {
+        self.skip_whitespace();
+        if self.peek() == Some('!') && self.peek_at(1) != Some('=') {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.filter_unary()?)));
+        }
+        if self.eat('(') {
+            let filter = self.filter_or()?;
+            self.skip_whitespace();
+            self.expect(')')?;
+            return Ok(filter);
+        }
+
+        let left = self.operand()?;
+        self.skip_whitespace();
+        let comparison = if self.eat_str("==") {
+            Comparison::Eq
+        } else if self.eat_str("!=") {
+            Comparison::Ne
+        } else if self.eat_str("<=") {
+            Comparison::Le
+        } else if self.eat_str(">=") {
+            Comparison::Ge
+        } else if self.eat('<') {
+            Comparison::Lt
+        } else if self.eat('>') {
+            Comparison::Gt
+        } else if matches!(left, Operand::Literal(_)) {
+            return Err(self.error("expected a comparison"));
+        } else {
+            return Ok(Filter::Exists(left));
+        };
+        self.skip_whitespace();
+        Ok(Filter::Compare(left, comparison, self.operand()?))
+    }
+
+    fn operand(&mut self) -> SoclessResult<Operand> {
+        match self.peek() {
+            Some('@') => {
+                self.pos += 1;
+                Ok(Operand::Current(self.path(true)?))
+            }
+            Some('$') => {
+                self.pos += 1;
+                Ok(Operand::Root(self.path(true)?))
+            }
+            Some('\'') | Some('"') => Ok(Operand::Literal(Value::String(self.quoted_string()?))),
+            _ => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .map_or(false, |c| c.is_ascii_alphanumeric() || "-+.".contains(c))
+                {
+                    self.pos += 1;
+                }
+                serde_json::from_str(&self.source_since(start))
+                    .map(Operand::Literal)
+                    .map_err(|_| self.error("expected a literal, '@' or '$'"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn select(path: &str, root: &Value) -> Vec<Value> {
+        JsonPath::parse(path)
+            .unwrap()
+            .select(root)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    fn mock_root() -> Value {
+        json!({
+            "details": {
+                "name": "Leshy",
+                "items": ["camera", {"pin": 1234}, {"pin": 5678, "owner": "Archer"}],
+                "dotted.key": "dots",
+                "users": [
+                    {"name": "Sterling", "age": 35},
+                    {"name": "Cheryl", "age": 17},
+                    {"name": "Malory", "age": 60}
+                ]
+            }
+        })
+    }
+
+    #[test]
+    fn test_simple_path_is_singular() {
+        let path = JsonPath::parse("$.details.name").unwrap();
+        assert!(path.is_singular());
+        assert_eq!(select("$.details.name", &mock_root()), vec![json!("Leshy")]);
+    }
+
+    #[test]
+    fn test_indexes() {
+        let root = mock_root();
+        assert_eq!(select("$.details.items[0]", &root), vec![json!("camera")]);
+        assert_eq!(select("$.details.items[1].pin", &root), vec![json!(1234)]);
+        assert_eq!(
+            select("$.details.items[-1].owner", &root),
+            vec![json!("Archer")]
+        );
+        assert!(select("$.details.items[7]", &root).is_empty());
+        assert!(JsonPath::parse("$.details.items[1].pin")
+            .unwrap()
+            .is_singular());
+    }
+
+    #[test]
+    fn test_quoted_keys() {
+        let root = mock_root();
+        assert_eq!(
+            select("$.details['dotted.key']", &root),
+            vec![json!("dots")]
+        );
+        assert_eq!(
+            select("$[\"details\"]['name']", &root),
+            vec![json!("Leshy")]
+        );
+    }
+
+    #[test]
+    fn test_slices() {
+        let root = json!({"n": [0, 1, 2, 3, 4, 5]});
+        assert_eq!(select("$.n[1:3]", &root), vec![json!(1), json!(2)]);
+        assert_eq!(select("$.n[:2]", &root), vec![json!(0), json!(1)]);
+        assert_eq!(select("$.n[-2:]", &root), vec![json!(4), json!(5)]);
+        assert_eq!(
+            select("$.n[::2]", &root),
+            vec![json!(0), json!(2), json!(4)]
+        );
+        assert_eq!(
+            select("$.n[::-2]", &root),
+            vec![json!(5), json!(3), json!(1)]
+        );
+        assert!(!JsonPath::parse("$.n[1:3]").unwrap().is_singular());
+    }
+
+    #[test]
+    fn test_wildcards_and_unions() {
+        let root = mock_root();
+        assert_eq!(
+            select("$.details.users[*].name", &root),
+            vec![json!("Sterling"), json!("Cheryl"), json!("Malory")]
+        );
+        assert_eq!(
+            select("$.details.users[0,2].age", &root),
+            vec![json!(35), json!(60)]
+        );
+        assert_eq!(
+            select("$.details.items.*.pin", &root),
+            vec![json!(1234), json!(5678)]
+        );
+    }
+
+    #[test]
+    fn test_recursive_descent() {
+        let root = mock_root();
+        assert_eq!(select("$..pin", &root), vec![json!(1234), json!(5678)]);
+        assert_eq!(select("$..users[0].name", &root), vec![json!("Sterling")]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let root = mock_root();
+        assert_eq!(
+            select("$.details.users[?(@.age >= 18)].name", &root),
+            vec![json!("Sterling"), json!("Malory")]
+        );
+        assert_eq!(
+            select(
+                "$.details.users[?(@.age > 18 && @.name != 'Malory')].name",
+                &root
+            ),
+            vec![json!("Sterling")]
+        );
+        assert_eq!(
+            select(
+                "$.details.users[?(@.name == \"Cheryl\" || @.age == 60)].age",
+                &root
+            ),
+            vec![json!(17), json!(60)]
+        );
+        assert_eq!(
+            select("$.details.items[?(@.owner)].pin", &root),
+            vec![json!(5678)]
+        );
+        assert_eq!(
+            select("$.details.items[?(!@.owner)]", &root),
+            vec![json!("camera"), json!({"pin": 1234})]
+        );
+    }
+
+    #[test]
+    fn test_invalid_paths() {
+        for path in [
+            "details",
+            "$.",
+            "$.items[",
+            "$.items[0",
+            "$['unterminated]",
+            "$.a[?(@.b ==)]",
+        ] {
+            assert!(
+                matches!(JsonPath::parse(path), Err(SoclessError::Resolution(_))),
+                "{} should not parse",
+                path
+            );
+        }
+    }
+}
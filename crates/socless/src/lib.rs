@@ -11,21 +11,46 @@
 //! SOCless allows users to write complex State Machines that can do more than pass a Step's
 //! output directly to the next step.
 pub mod clients;
+pub mod config;
 pub mod constants;
+pub mod conversions;
+pub mod deadline;
+pub mod encryption;
+pub mod errors;
 pub mod events;
 pub mod humaninteraction;
 pub mod integrations;
+pub mod jsonpath;
+pub mod logging;
 pub mod models;
+pub mod redaction;
 pub mod resolver;
+pub mod store;
+pub mod template;
 pub mod utils;
+pub mod vault;
 
+pub use async_trait::async_trait;
 pub use clients::*;
+pub use config::{get_or_init_config, set_config, SoclessConfig};
+pub use deadline::{cancellation_token, DEADLINE_MARGIN};
+pub use encryption::{EncryptedVault, KeyProvider, KmsKeyProvider, LocalKeyProvider};
+pub use errors::{SoclessError, SoclessResult};
 pub use events::{create_events, SoclessEventBatch};
 pub use humaninteraction::{end_human_interaction, init_human_interaction};
-pub use integrations::socless_bootstrap;
+pub use integrations::{bootstrap_integration, socless_bootstrap, SoclessIntegration};
+pub use logging::init_json_logging;
 pub use models::{
-    EventTableItem, PlaybookArtifacts, PlaybookInput, ResponsesTableItem, ResultsTableItem,
-    SoclessEvent,
+    DedupTableItem, EventTableItem, LambdaInfo, PlaybookArtifacts, PlaybookInput,
+    ResponsesTableItem, ResultDigest, ResultsTableItem, SoclessEvent, StateAttempt, StateFailure,
+    StateMetadata,
 };
+pub use redaction::{Redactor, Secret};
 pub use resolver::{SoclessContext, SoclessLambdaInput, StateConfig};
-pub use utils::{gen_datetimenow, gen_id, get_item_from_table};
+pub use store::{get_or_init_store, set_store, DynamoStore, MemoryStore, SoclessStore};
+pub use utils::{gen_datetimenow, gen_id, get_item, get_item_from_table, ReadConsistency};
+pub use vault::{
+    delete_from_vault, fetch_bytes_from_vault, fetch_stream_from_vault, fetch_utf8_from_vault,
+    list_vault, save_to_vault, set_vault_backend, vault_reader, LocalVault, S3Vault, VaultBackend,
+    VaultObject,
+};
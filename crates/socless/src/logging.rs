@@ -0,0 +1,48 @@
+//! Structured logs for CloudWatch.
+//!
+//! socless logs with [`tracing`]. [`bootstrap_integration`] runs each State in a `socless_state`
+//! span carrying its `state_name`, `execution_id`, `investigation_id` and Lambda `request_id`,
+//! and table, vault and Step Functions calls get their own spans inside it, so every log line of
+//! a playbook execution can be found by its `execution_id` across the Lambdas it ran in.
+//!
+//! Call [`init_json_logging`] at the start of `main` to print them as one JSON object per line,
+//! filtered by `RUST_LOG` (default `info`):
+//! ```ignore
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     socless::init_json_logging();
+//!     lambda_runtime::run(handler_fn(handler)).await
+//! }
+//! ```
+//!
+//! [`bootstrap_integration`]: crate::integrations::bootstrap_integration
+
+use tracing_subscriber::EnvFilter;
+
+/// Install a global subscriber printing JSON lines with the current span and its parents.
+///
+/// Returns `false` if a global subscriber was already installed, which is kept.
+pub fn init_json_logging() -> bool {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt()
+        .json()
+        .with_env_filter(filter)
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_ansi(false)
+        .try_init()
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_json_logging_once() {
+        // another test may have installed a subscriber first
+        init_json_logging();
+        assert!(!init_json_logging());
+        tracing::info!(execution_id = "exec-1", "logged as json");
+    }
+}
@@ -1,6 +1,7 @@
+use lambda_runtime::Context;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct SoclessEvent {
@@ -17,7 +18,7 @@ pub struct SoclessEvent {
     pub dedup_keys: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ResultsTableItem {
     pub execution_id: String,
     pub investigation_id: String,
@@ -30,6 +31,14 @@ pub struct PlaybookInput {
     pub artifacts: PlaybookArtifacts,
     pub results: HashMap<String, Value>,
     pub errors: HashMap<String, Value>,
+    /// Each State's attempt log, kept when `record_attempts` is set in the [`SoclessConfig`].
+    ///
+    /// [`SoclessConfig`]: crate::config::SoclessConfig
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub attempts: HashMap<String, Vec<StateAttempt>>,
+    /// The Lambda invocation that saved each State's results.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, StateMetadata>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -81,3 +90,98 @@ pub struct ResponsesTableItem {
     pub receiver: String,
     pub await_token: String,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DedupTableItem {
+    pub dedup_hash: String,
+    pub current_investigation_id: String,
+}
+
+/// A failed State, saved under the State's name in the playbook's `errors` map.
+///
+/// Displays as a json object so a playbook `Catch` can parse the `Cause` with `States.StringToJson`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct StateFailure {
+    pub state_name: String,
+    pub error_type: String,
+    pub message: String,
+    pub timestamp: String,
+}
+
+impl fmt::Display for StateFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let payload = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        write!(f, "{}", payload)
+    }
+}
+
+/// One run of a State. Step Functions retries re-run the State, each run is appended to the
+/// State's attempt log in the playbook's `attempts` map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct StateAttempt {
+    /// Starts at 1 for the first run of the State in the execution.
+    pub attempt: usize,
+    pub started_at: String,
+    pub ended_at: String,
+    /// The request id of the Lambda invocation that ran the State.
+    pub request_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<ResultDigest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<StateFailure>,
+}
+
+/// The size and md5 of a State's result as saved, kept in its attempt log in place of the result
+/// so retries of a State with a large output don't fill the results item.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ResultDigest {
+    /// Size of the result serialized as json.
+    pub bytes: usize,
+    /// Hex md5 of the result serialized as json.
+    pub md5: String,
+    /// The `vault:` reference the result was offloaded to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vault_ref: Option<String>,
+}
+
+impl ResultDigest {
+    pub fn of(result: &Value) -> Self {
+        let serialized = result.to_string();
+        ResultDigest {
+            bytes: serialized.len(),
+            md5: format!("{:x}", md5::compute(&serialized)),
+            vault_ref: None,
+        }
+    }
+}
+
+/// The Lambda invocation running a State, from its `lambda_runtime::Context`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct LambdaInfo {
+    pub request_id: String,
+    pub function_arn: String,
+    /// Milliseconds since the unix epoch, when Lambda stops the invocation.
+    pub deadline_ms: u64,
+    pub log_group: String,
+    pub log_stream: String,
+}
+
+impl From<&Context> for LambdaInfo {
+    fn from(context: &Context) -> Self {
+        LambdaInfo {
+            request_id: context.request_id.clone(),
+            function_arn: context.invoked_function_arn.clone(),
+            deadline_ms: context.deadline,
+            log_group: context.env_config.log_group.clone(),
+            log_stream: context.env_config.log_stream.clone(),
+        }
+    }
+}
+
+/// When and by which Lambda invocation a State's results were saved, kept under the State's name
+/// in the playbook's `metadata` map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct StateMetadata {
+    pub saved_at: String,
+    pub lambda: LambdaInfo,
+}
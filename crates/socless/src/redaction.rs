@@ -0,0 +1,222 @@
+//! Keeping credentials out of logs and the results table.
+//!
+//! - [`Secret`] wraps a value so it prints as `[REDACTED]`. Handlers receive their parameters
+//!   as plain json or as their own `Input` type, so socless can't wrap vault content for them:
+//!   integration authors opt in by declaring the parameters they resolve from `vault:`
+//!   references as `Secret`, which keeps them out of the handler's own logs:
+//!   ```
+//!   use serde::Deserialize;
+//!   use socless::redaction::Secret;
+//!
+//!   #[derive(Debug, Deserialize)]
+//!   struct Params {
+//!       username: String,
+//!       api_key: Secret<String>, // "vault:okta_api_key"
+//!   }
+//!
+//!   let params: Params =
+//!       serde_json::from_str(r#"{"username": "sterling", "api_key": "hunter2"}"#).unwrap();
+//!   assert_eq!(params.api_key.expose(), "hunter2");
+//!   assert!(!format!("{:?}", params).contains("hunter2"));
+//!   ```
+//! - [`Redactor`] scrubs the values of keys matching the `redact_keys` patterns of the
+//!   [`SoclessConfig`] from json, which socless does for every [`SoclessContext`] and resolved
+//!   parameters it logs, and for saved State results when `redact_saved_results` is set.
+//!   Parameters whose resolution read a vault file, be it through a `vault:` reference, a `$.`
+//!   path or an offloaded State result, are always redacted from the logs socless writes.
+//!
+//! [`SoclessConfig`]: crate::config::SoclessConfig
+//! [`SoclessContext`]: crate::resolver::SoclessContext
+
+use crate::config::{SoclessConfig, SOCLESS_CONFIG};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// The `redact_keys` of the default [`SoclessConfig`].
+pub const DEFAULT_REDACT_KEYS: [&str; 4] = ["*password*", "*secret*", "*token*", "*credential*"];
+
+/// A value that is never printed: `Debug` and `Display` show `[REDACTED]`. It (de)serializes as
+/// the wrapped value, so it can be used in place of `T` in an integration's `Input` and `Output`.
+#[derive(Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    /// The secret value, to use it and not to log it.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Scrubs the values of keys whose name matches one of its patterns from json.
+///
+/// Patterns are case-insensitive and `*` matches any characters, so `*token*` matches
+/// `task_token` and `TokenId` while `password` only matches `password`.
+/// # Example
+/// ```
+/// use serde_json::json;
+/// use socless::redaction::Redactor;
+///
+/// let redactor = Redactor::new(&["*token*", "password"]);
+/// assert_eq!(
+///     redactor.redact(&json!({"user": {"name": "sterling", "password": "guest"}, "api_token": "abc"})),
+///     json!({"user": {"name": "sterling", "password": "[REDACTED]"}, "api_token": "[REDACTED]"})
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Redactor {
+    patterns: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Self {
+        Redactor {
+            patterns: patterns
+                .iter()
+                .map(|pattern| pattern.as_ref().to_lowercase())
+                .collect(),
+        }
+    }
+
+    pub fn from_config(config: &SoclessConfig) -> Self {
+        Self::new(&config.redact_keys)
+    }
+
+    /// The redactor of the global config if it is loaded, otherwise of the default config. Doesn't
+    /// wait for the config, so it can be used in `Debug` implementations.
+    pub fn current() -> Self {
+        match SOCLESS_CONFIG.get() {
+            Some(config) => Self::from_config(config),
+            None => Self::new(&DEFAULT_REDACT_KEYS),
+        }
+    }
+
+    pub fn is_sensitive_key(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.patterns
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &key))
+    }
+
+    /// `value` with the values of sensitive keys, at any depth, replaced by `[REDACTED]`.
+    pub fn redact(&self, value: &Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, value)| {
+                        let value = if self.is_sensitive_key(key) {
+                            Value::String(REDACTED.to_owned())
+                        } else {
+                            self.redact(value)
+                        };
+                        (key.to_owned(), value)
+                    })
+                    .collect::<Map<String, Value>>(),
+            ),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|item| self.redact(item)).collect())
+            }
+            other => other.to_owned(),
+        }
+    }
+}
+
+/// Match `key` against `pattern`, where `*` matches any (possibly empty) run of characters.
+fn matches_pattern(pattern: &str, key: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let mut rest = match parts.next().and_then(|prefix| key.strip_prefix(prefix)) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    match parts.split_last() {
+        None => rest.is_empty(),
+        Some((suffix, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(index) => rest = &rest[index + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.ends_with(suffix)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("password", "password"));
+        assert!(!matches_pattern("password", "password_hint"));
+        assert!(matches_pattern("*token*", "task_token"));
+        assert!(matches_pattern("*token*", "token"));
+        assert!(matches_pattern("api_*", "api_key"));
+        assert!(!matches_pattern("api_*", "okta_api_key"));
+        assert!(matches_pattern("*_key", "okta_api_key"));
+        assert!(matches_pattern("a*b*c", "a_b_b_c"));
+        assert!(!matches_pattern("a*b*c", "a_c_b"));
+        assert!(!matches_pattern("*ab*ab*", "xabx"));
+    }
+
+    #[test]
+    fn test_redactor_is_case_insensitive_and_recursive() {
+        let redactor = Redactor::new(&DEFAULT_REDACT_KEYS);
+
+        let redacted = redactor.redact(&json!({
+            "TaskToken": "AAAA",
+            "users": [{"name": "sterling", "Password": "guest"}],
+            "count": 2
+        }));
+
+        assert_eq!(
+            redacted,
+            json!({
+                "TaskToken": REDACTED,
+                "users": [{"name": "sterling", "Password": REDACTED}],
+                "count": 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_secret_is_never_printed() {
+        let secret: Secret<String> = serde_json::from_value(json!("hunter2")).unwrap();
+
+        assert_eq!(format!("{:?} {}", secret, secret), "[REDACTED] [REDACTED]");
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(serde_json::to_value(&secret).unwrap(), json!("hunter2"));
+    }
+}
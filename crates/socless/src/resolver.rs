@@ -1,34 +1,59 @@
-use std::{collections::HashMap, env};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use async_recursion::async_recursion;
 use serde::{Deserialize, Serialize};
-use serde_dynamo::from_item;
 use serde_json::{from_value, json, to_value, Value};
+use tracing::{info, instrument};
 
 use crate::{
-    constants::RESULTS_TABLE_ENV,
-    get_item_from_table,
-    utils::{fetch_utf8_from_vault, json_merge},
-    PlaybookArtifacts, ResultsTableItem,
+    conversions::{apply_conversion, is_conversion},
+    errors::{SoclessError, SoclessResult},
+    jsonpath::JsonPath,
+    redaction::Redactor,
+    template::{is_template, render_with},
+    vault::{fetch_bytes_from_vault, fetch_from_vault, OFFLOADED_RESULT_CONTENT_TYPE, VAULT_TOKEN},
+    LambdaInfo, PlaybookArtifacts,
 };
 
-const VAULT_TOKEN: &str = "vault:";
 const PATH_TOKEN: &str = "$.";
 const CONVERSION_TOKEN: &str = "!";
+const BASE64_CONVERSION: &str = "base64";
 
 pub async fn resolve_parameters(
     params: &HashMap<String, Value>,
     socless_context: &SoclessContext,
-) -> HashMap<String, Value> {
+) -> SoclessResult<HashMap<String, Value>> {
+    let (resolved_parameters, _) =
+        resolve_parameters_reading_vault(params, socless_context).await?;
+    Ok(resolved_parameters)
+}
+
+/// Resolve the parameters, and return the names of those whose resolution read a vault file to
+/// keep their values out of logs, whatever the reference: `vault:file`, a `$.` path through a
+/// `vault:` value or an offloaded State result.
+pub async fn resolve_parameters_reading_vault(
+    params: &HashMap<String, Value>,
+    socless_context: &SoclessContext,
+) -> SoclessResult<(HashMap<String, Value>, HashSet<String>)> {
     let mut resolved_parameters = HashMap::new();
+    let mut vault_parameters = HashSet::new();
     for (parameter, reference) in params {
+        let read_vault = AtomicBool::new(false);
         resolved_parameters.insert(
             parameter.to_owned(),
-            resolve_reference(&reference, socless_context).await,
+            resolve(reference, socless_context, &read_vault).await?,
         );
+        if read_vault.into_inner() {
+            vault_parameters.insert(parameter.to_owned());
+        }
     }
 
-    resolved_parameters
+    Ok((resolved_parameters, vault_parameters))
 }
 
 /// The SOCless Event structure required to run a SOCless integration lambda function
@@ -56,25 +81,40 @@ pub struct SoclessLambdaInput {
 }
 
 impl SoclessLambdaInput {
-    pub async fn resolve_state_config_parameters(&mut self, socless_context: &SoclessContext) {
-        self.state_config.parameters =
-            resolve_parameters(&self.state_config.parameters, socless_context).await;
+    /// Resolve the State's parameters in place, returning the names of those read from the
+    /// vault, see [`resolve_parameters_reading_vault`].
+    #[instrument(level = "debug", skip_all)]
+    pub async fn resolve_state_config_parameters(
+        &mut self,
+        socless_context: &SoclessContext,
+    ) -> SoclessResult<HashSet<String>> {
+        let (parameters, vault_parameters) =
+            resolve_parameters_reading_vault(&self.state_config.parameters, socless_context)
+                .await?;
+        self.state_config.parameters = parameters;
+        Ok(vault_parameters)
     }
 }
 
-impl From<Value> for SoclessLambdaInput {
-    fn from(event: Value) -> Self {
+impl TryFrom<Value> for SoclessLambdaInput {
+    type Error = SoclessError;
+
+    fn try_from(event: Value) -> SoclessResult<Self> {
         let mut socless_event: SoclessLambdaInput = match from_value((&event).to_owned()) {
             Ok(correct_event) => correct_event,
             Err(_e) => {
-                println!(
+                info!(
                     "Event missing StateConfig, attempting to build Event as direct_invoke mode."
                 );
                 SoclessLambdaInput {
                     state_config: StateConfig {
                         name: "direct_invoke".to_string(),
-                        parameters: from_value(event)
-                            .expect("unable to convert entire event to 'Parameters' hashmap"),
+                        parameters: from_value(event).map_err(|e| {
+                            SoclessError::InvalidInput(format!(
+                                "unable to convert entire event to 'Parameters' hashmap: {}",
+                                e
+                            ))
+                        })?,
                         ..Default::default()
                     },
                     ..Default::default()
@@ -83,19 +123,24 @@ impl From<Value> for SoclessLambdaInput {
         };
 
         if let Some(token) = socless_event.task_token {
+            let sfn_context = socless_event.sfn_context.ok_or_else(|| {
+                SoclessError::InvalidInput(
+                    "'sfn_context' not found in socless event with a 'task_token'".to_owned(),
+                )
+            })?;
             socless_event = SoclessLambdaInput {
                 task_token: Some(token),
-                ..from_value(
-                    socless_event
-                        .sfn_context
-                        .expect("'sfn_context' not found in socless event with a 'task_token'"),
-                )
-                .expect("'sfn_context' object does not deserialize into a SoclessLambdaInput type")
+                ..from_value(sfn_context).map_err(|e| {
+                    SoclessError::InvalidInput(format!(
+                        "'sfn_context' object does not deserialize into a SoclessLambdaInput type: {}",
+                        e
+                    ))
+                })?
             }
         }
 
         if socless_event.execution_id.is_none() && socless_event.artifacts.is_none() {
-            println!(
+            info!(
                 "No State_Config was passed to the integration, likely due to invocation \
             from outside of a SOCless playbook. Running this lambda in test mode."
             );
@@ -108,7 +153,7 @@ impl From<Value> for SoclessLambdaInput {
             // };
         }
 
-        socless_event
+        Ok(socless_event)
     }
 }
 
@@ -123,12 +168,19 @@ pub struct StateConfig {
 }
 
 impl StateConfig {
-    pub async fn resolve_parameters(&mut self, socless_context: &SoclessContext) {
-        self.parameters = resolve_parameters(&self.parameters, socless_context).await;
+    pub async fn resolve_parameters(
+        &mut self,
+        socless_context: &SoclessContext,
+    ) -> SoclessResult<()> {
+        self.parameters = resolve_parameters(&self.parameters, socless_context).await?;
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+/// The playbook's global state a State's parameters are resolved against.
+///
+/// Prints as json with the values of sensitive keys redacted, see [`Redactor::current`].
+#[derive(Clone, Serialize, Deserialize, Default)]
 pub struct SoclessContext {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub execution_id: Option<String>,
@@ -142,10 +194,20 @@ pub struct SoclessContext {
     pub task_token: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub state_name: Option<String>,
+    /// The Lambda invocation running the State, set by `bootstrap_integration`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lambda: Option<LambdaInfo>,
     #[serde(flatten)]
     pub other: HashMap<String, Value>,
 }
 
+impl fmt::Debug for SoclessContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let context = to_value(self).map_err(|_| fmt::Error)?;
+        write!(f, "SoclessContext {}", Redactor::current().redact(&context))
+    }
+}
+
 /// Evaluate a reference path and return the referenced value
 /// ### Example
 /// ```
@@ -164,50 +226,98 @@ pub struct SoclessContext {
 /// let result =
 /// # tokio_test::block_on(
 /// resolve_reference(&json!([{"firstname": "$.artifacts.event.details.firstname"}, "$.artifacts.event.details.lastname"]), &root_object)
-/// );
+/// ).unwrap();
 /// let expected_result = json!([{"firstname": "Sterling"}, "Archer"]);
 /// assert_eq!(result, expected_result);
 /// ```
+pub async fn resolve_reference(
+    reference_path: &Value,
+    root_obj: &SoclessContext,
+) -> SoclessResult<Value> {
+    resolve(reference_path, root_obj, &AtomicBool::new(false)).await
+}
+
+/// [`resolve_reference`], setting `read_vault` if a vault file was read.
 #[async_recursion]
-pub async fn resolve_reference(reference_path: &Value, root_obj: &SoclessContext) -> Value {
-    if reference_path.is_object() {
+async fn resolve(
+    reference_path: &Value,
+    root_obj: &SoclessContext,
+    read_vault: &AtomicBool,
+) -> SoclessResult<Value> {
+    if let Some(reference_map) = reference_path.as_object() {
         let mut resolved_dict: HashMap<String, Value> = HashMap::new();
-        for (key, value) in reference_path.as_object().unwrap() {
-            resolved_dict.insert(key.to_owned(), resolve_reference(value, root_obj).await);
+        for (key, value) in reference_map {
+            resolved_dict.insert(key.to_owned(), resolve(value, root_obj, read_vault).await?);
         }
 
-        to_value(resolved_dict).unwrap()
-    } else if reference_path.is_array() {
+        Ok(to_value(resolved_dict)?)
+    } else if let Some(reference_list) = reference_path.as_array() {
         let mut resolved_list: Vec<Value> = vec![];
-        for item in reference_path.as_array().unwrap() {
-            resolved_list.push(resolve_reference(item, root_obj).await);
+        for item in reference_list {
+            resolved_list.push(resolve(item, root_obj, read_vault).await?);
         }
 
-        to_value(resolved_list).unwrap()
-    } else if reference_path.is_string() {
-        let ref_string = reference_path.as_str().unwrap();
+        Ok(to_value(resolved_list)?)
+    } else if let Some(ref_string) = reference_path.as_str() {
+        let (trimmed_ref, conversion) = split_conversion(ref_string).await?;
+        let trimmed_ref = trimmed_ref.to_string();
 
-        let (trimmed_ref, _conversion) = match ref_string.split_once(CONVERSION_TOKEN) {
-            Some((trimmed_ref, conversion)) => (trimmed_ref.to_string(), Some(conversion)),
-            None => (ref_string.to_string(), None),
-        };
+        if conversion == Some(BASE64_CONVERSION) && trimmed_ref.starts_with(VAULT_TOKEN) {
+            // encode the raw bytes, so binary files aren't base64 encoded twice
+            read_vault.store(true, Ordering::Relaxed);
+            let content = fetch_bytes_from_vault(vault_file_id(&trimmed_ref)?).await?;
+            return Ok(Value::String(base64::encode(content)));
+        }
 
-        let value_before_convert = if trimmed_ref.starts_with(VAULT_TOKEN) {
-            to_value(resolve_vault_path(&trimmed_ref).await).unwrap()
+        let value_before_convert = if is_template(&trimmed_ref) {
+            render_with(&trimmed_ref, &json!({ "context": root_obj }), |string| {
+                resolve_vault_value(Value::String(string), read_vault)
+            })
+            .await?
+        } else if trimmed_ref.starts_with(VAULT_TOKEN) {
+            resolve_vault_path(&trimmed_ref, read_vault).await?
         } else if trimmed_ref.starts_with(PATH_TOKEN) {
-            resolve_json_path(&trimmed_ref, root_obj).await
+            resolve_path(&trimmed_ref, root_obj, read_vault).await?
         } else {
-            to_value(trimmed_ref).unwrap()
+            to_value(trimmed_ref)?
         };
 
-        ////! only json conversions currently supported and I THINK serde_json is automatically doing that
-        // return match _conversion {
-        //     Some(conversion_key) => apply_conversion(value_before_convert, conversion_key),
-        //     None => value_before_convert,
-        // };
-        value_before_convert
+        match conversion {
+            Some(conversion) => apply_conversion(value_before_convert, conversion).await,
+            None => Ok(value_before_convert),
+        }
     } else {
-        reference_path.to_owned()
+        Ok(reference_path.to_owned())
+    }
+}
+
+/// Split a trailing `!conversion` off of a reference, e.g. `vault:file.json!json`.
+///
+/// Only a registered conversion name after the last `!` of a `vault:`, `$.` or `{{ }}` reference
+/// is split off, so JsonPath filters like `$.items[?(@.status != 'closed')]` and literal strings
+/// like `Hello!` or `All clear!bool` are left intact.
+async fn split_conversion(ref_string: &str) -> SoclessResult<(&str, Option<&str>)> {
+    let (trimmed_ref, conversion) = match ref_string.rsplit_once(CONVERSION_TOKEN) {
+        Some(split) => split,
+        None => return Ok((ref_string, None)),
+    };
+    let is_reference = trimmed_ref.starts_with(VAULT_TOKEN)
+        || trimmed_ref.starts_with(PATH_TOKEN)
+        || is_template(trimmed_ref);
+    let is_word = !conversion.is_empty()
+        && conversion
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_reference || !is_word {
+        return Ok((ref_string, None));
+    }
+
+    match is_conversion(conversion).await {
+        true => Ok((trimmed_ref, Some(conversion))),
+        false => Err(SoclessError::Resolution(format!(
+            "unknown conversion !{} in {}",
+            conversion, ref_string
+        ))),
     }
 }
 
@@ -215,40 +325,93 @@ pub async fn resolve_reference(reference_path: &Value, root_obj: &SoclessContext
 ///
 /// This handles vault references e.g `vault:file_name` that are passed
 /// in as parameters to Socless integrations. It fetches and returns the content
-/// of the Vault object with name `file_name` in the vault.
-async fn resolve_vault_path(reference_path: &str) -> String {
-    let (_, file_id) = reference_path.split_once(VAULT_TOKEN).unwrap();
-    let data = fetch_utf8_from_vault(&file_id).await;
-    data
+/// of the Vault object with name `file_name` in the vault, base64 encoded if it isn't utf8.
+/// State results offloaded to the vault are returned as the original json.
+async fn resolve_vault_path(reference_path: &str, read_vault: &AtomicBool) -> SoclessResult<Value> {
+    read_vault.store(true, Ordering::Relaxed);
+    let vault_content = fetch_from_vault(vault_file_id(reference_path)?).await?;
+    if vault_content.content_type.as_deref() == Some(OFFLOADED_RESULT_CONTENT_TYPE) {
+        return Ok(serde_json::from_slice(&vault_content.content)?);
+    }
+    Ok(Value::String(vault_content_to_string(
+        vault_content.content,
+    )))
+}
+
+fn vault_file_id(reference_path: &str) -> SoclessResult<&str> {
+    let (_, file_id) = reference_path.split_once(VAULT_TOKEN).ok_or_else(|| {
+        SoclessError::Resolution(format!("{} is not a vault reference", reference_path))
+    })?;
+    Ok(file_id)
+}
+
+/// Text files as is, binary files (images, pcaps, archives...) as base64.
+fn vault_content_to_string(content: Vec<u8>) -> String {
+    String::from_utf8(content).unwrap_or_else(|e| base64::encode(e.into_bytes()))
 }
 
 /// Resolves a JsonPath reference to the actual value referenced.
 ///
 /// reference_path = "$.artifacts.investigation_id"
 ///
-/// Does not support the full JsonPath specification.
-async fn resolve_json_path(reference_path: &str, root_obj: &SoclessContext) -> Value {
-    let (_pre, post) = reference_path.split_once(PATH_TOKEN).unwrap();
+/// See [`crate::jsonpath`] for the supported syntax. Singular paths (only names and indexes)
+/// return the matched value and error if it doesn't exist, other paths return an array of every
+/// match. Vault references found along a singular path are replaced by the vault file content.
+/// Other paths leave `vault:` strings as is, since a wildcard or `..` could otherwise fetch every
+/// vault file under the matched subtree.
+pub async fn resolve_json_path(
+    reference_path: &str,
+    root_obj: &SoclessContext,
+) -> SoclessResult<Value> {
+    resolve_path(reference_path, root_obj, &AtomicBool::new(false)).await
+}
 
-    let mut obj_copy = to_value(root_obj).unwrap();
+/// [`resolve_json_path`], setting `read_vault` if a vault file was read.
+async fn resolve_path(
+    reference_path: &str,
+    root_obj: &SoclessContext,
+    read_vault: &AtomicBool,
+) -> SoclessResult<Value> {
+    let json_path = JsonPath::parse(reference_path)?;
+    let is_singular = json_path.is_singular();
+    let root = to_value(root_obj)?;
+
+    let mut nodes = vec![root.clone()];
+    for segment in json_path.segments() {
+        let mut selected = vec![];
+        for node in &nodes {
+            for value in segment.select(node, &root) {
+                selected.push(match is_singular {
+                    true => resolve_vault_value(value.to_owned(), read_vault).await?,
+                    false => value.to_owned(),
+                });
+            }
+        }
 
-    for key in post.split('.') {
-        let mut value = obj_copy[key].to_owned();
-        if value.is_null() {
-            panic!(
+        if is_singular && selected.first().map_or(true, Value::is_null) {
+            return Err(SoclessError::Resolution(format!(
                 "Unable to resolve key {}, parent object does not exist. Full path: {}",
-                key, reference_path
-            );
-        } else {
-            if let Some(string_value) = value.as_str() {
-                if string_value.starts_with(VAULT_TOKEN) {
-                    value = to_value(resolve_vault_path(string_value).await).unwrap();
-                }
-            }
-            obj_copy = value;
+                segment.key(),
+                reference_path
+            )));
         }
+        nodes = selected;
+    }
+
+    match is_singular {
+        true => Ok(nodes.swap_remove(0)),
+        false => Ok(Value::Array(nodes)),
+    }
+}
+
+/// Replace a `vault:` string with the vault file content, leaving any other value as is.
+async fn resolve_vault_value(value: Value, read_vault: &AtomicBool) -> SoclessResult<Value> {
+    match value.as_str() {
+        Some(string_value) if string_value.starts_with(VAULT_TOKEN) => {
+            resolve_vault_path(string_value, read_vault).await
+        }
+        _ => Ok(value),
     }
-    obj_copy
 }
 
 #[cfg(test)]
@@ -267,7 +430,7 @@ pub fn mock_event_value_boilerplate() -> Value {
                         "a_map": {
                             "jfutz" : "littleboyblew"
                         },
-                        // "vault_test" : "vault:socless_vault_tests.txt"
+                        "vault_test" : "vault:socless_vault_tests.txt"
                     },
                     "event_type": "mock_test_event",
                     "event_meta": {},
@@ -284,8 +447,8 @@ pub fn mock_event_value_boilerplate() -> Value {
                     "firstname": "$.artifacts.event.details.firstname",
                     "lastname": "$.artifacts.event.details.lastname",
                     "middlename": "Malory",
-                    // "vault.txt": "vault:socless_vault_tests.txt",  // requires mocking s3 vault
-                    // "vault.json": "vault:socless_vault_tests.json!json",
+                    "vault.txt": "vault:socless_vault_tests.txt",
+                    "vault.json": "vault:socless_vault_tests.json!json",
                     "acquaintances": [
                         {
                             "firstname": "$.artifacts.event.details.middlename",
@@ -367,14 +530,16 @@ mod tests {
             "channel_id": "C123458"
         });
 
-        let _test = SoclessLambdaInput::from(mock_event_data);
+        let _test = SoclessLambdaInput::try_from(mock_event_data).unwrap();
     }
 
     #[tokio::test]
     async fn test_resolve_jsonpath_string() {
         let mock_root_obj: SoclessContext = build_mock_root_obj();
 
-        let result = resolve_json_path("$.artifacts.event.details.firstname", &mock_root_obj).await;
+        let result = resolve_json_path("$.artifacts.event.details.firstname", &mock_root_obj)
+            .await
+            .unwrap();
         assert_eq!(
             result,
             to_value(mock_root_obj).unwrap()["artifacts"]["event"]["details"]["firstname"]
@@ -385,18 +550,293 @@ mod tests {
     async fn test_resolve_jsonpath_map() {
         let mock_root_obj: SoclessContext = build_mock_root_obj();
 
-        let result = resolve_json_path("$.artifacts.event.details.a_map", &mock_root_obj).await;
+        let result = resolve_json_path("$.artifacts.event.details.a_map", &mock_root_obj)
+            .await
+            .unwrap();
         assert_eq!(
             result,
             to_value(mock_root_obj).unwrap()["artifacts"]["event"]["details"]["a_map"]
         );
     }
 
+    #[tokio::test]
+    async fn test_resolve_jsonpath_missing_key() {
+        let mock_root_obj: SoclessContext = build_mock_root_obj();
+
+        let result = resolve_json_path("$.artifacts.event.details.not_a_key", &mock_root_obj).await;
+        assert!(matches!(result, Err(SoclessError::Resolution(_))));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_jsonpath_array_index_and_wildcard() {
+        let mock_root_obj: SoclessContext = from_value(json!({
+            "artifacts": {
+                "event": {
+                    "details": {
+                        "items": ["camera", {"pin": 1234}, {"pin": 5678}]
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let pin = resolve_json_path("$.artifacts.event.details.items[1].pin", &mock_root_obj)
+            .await
+            .unwrap();
+        assert_eq!(pin, json!(1234));
+
+        let pins = resolve_json_path("$.artifacts.event.details.items[*].pin", &mock_root_obj)
+            .await
+            .unwrap();
+        assert_eq!(pins, json!([1234, 5678]));
+
+        let no_matches = resolve_json_path("$..not_a_key", &mock_root_obj)
+            .await
+            .unwrap();
+        assert_eq!(no_matches, json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_jsonpath_slice_with_extreme_steps() {
+        let mock_root_obj: SoclessContext = from_value(json!({
+            "artifacts": { "n": [0, 1, 2, 3, 4, 5] }
+        }))
+        .unwrap();
+
+        let forward =
+            resolve_json_path(&format!("$.artifacts.n[1:5:{}]", i64::MAX), &mock_root_obj)
+                .await
+                .unwrap();
+        assert_eq!(forward, json!([1]));
+
+        let backward =
+            resolve_json_path(&format!("$.artifacts.n[4:0:{}]", i64::MIN), &mock_root_obj)
+                .await
+                .unwrap();
+        assert_eq!(backward, json!([4]));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reference_jsonpath_filter_is_not_a_conversion() {
+        let mock_root_obj: SoclessContext = from_value(json!({
+            "artifacts": {
+                "users": [{"name": "Sterling", "status": "open"}, {"name": "Cheryl", "status": "closed"}]
+            }
+        }))
+        .unwrap();
+
+        let result = resolve_reference(
+            &json!("$.artifacts.users[?(@.status != 'closed')].name"),
+            &mock_root_obj,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, json!(["Sterling"]));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reference_template() {
+        let mock_root_obj: SoclessContext = build_mock_root_obj();
+
+        let result = resolve_reference(
+            &json!({
+                "message": "User {{ context.artifacts.event.details.firstname }} {{context.artifacts.event.details.lastname | upper}} logged in",
+                "a_map": "{{ context.artifacts.event.details.a_map }}"
+            }),
+            &mock_root_obj,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            result,
+            json!({
+                "message": "User Sterling ARCHER logged in",
+                "a_map": {"jfutz": "littleboyblew"}
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reference_conversions() {
+        let mock_root_obj: SoclessContext = from_value(json!({
+            "artifacts": {
+                "age": "35",
+                "config": "{\"enabled\": true}",
+                "users": [{"name": "Sterling", "status": "open"}]
+            }
+        }))
+        .unwrap();
+
+        let result = resolve_reference(
+            &json!({
+                "age": "$.artifacts.age!int",
+                "config": "$.artifacts.config!json",
+                "names": "{{ context.artifacts.users | tojson }}!json",
+                "exclaimed": "Hello!",
+                "not_a_conversion": "Hello!world",
+                "literal": "{\"enabled\": true}!json",
+                "all_clear": "All clear!bool",
+                "steps": "Steps!lines"
+            }),
+            &mock_root_obj,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            result,
+            json!({
+                "age": 35,
+                "config": {"enabled": true},
+                "names": [{"name": "Sterling", "status": "open"}],
+                "exclaimed": "Hello!",
+                "not_a_conversion": "Hello!world",
+                "literal": "{\"enabled\": true}!json",
+                "all_clear": "All clear!bool",
+                "steps": "Steps!lines"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reference_unknown_conversion() {
+        let mock_root_obj: SoclessContext = build_mock_root_obj();
+
+        let result = resolve_reference(
+            &json!("$.artifacts.event.details.firstname!not_a_conversion"),
+            &mock_root_obj,
+        )
+        .await;
+        assert!(matches!(result, Err(SoclessError::Resolution(_))));
+    }
+
+    #[test]
+    fn test_vault_content_to_string() {
+        assert_eq!(
+            vault_content_to_string(b"plain text".to_vec()),
+            "plain text"
+        );
+        assert_eq!(
+            vault_content_to_string(vec![0x89, b'P', b'N', b'G', 0xff]),
+            "iVBOR/8="
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reference_vault() {
+        crate::vault::use_test_vault().await;
+        let mock_root_obj: SoclessContext = build_mock_root_obj();
+
+        let result = resolve_reference(
+            &json!({
+                "text": "vault:socless_vault_tests.txt",
+                "json": "vault:socless_vault_tests.json!json",
+                "lines": "vault:socless_vault_tests.txt!lines",
+                "nested": "$.artifacts.event.details.vault_test"
+            }),
+            &mock_root_obj,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            result,
+            json!({
+                "text": "this came from the vault",
+                "json": {"hello": "world"},
+                "lines": ["this came from the vault"],
+                "nested": "this came from the vault"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_jsonpath_rehydrates_offloaded_results() {
+        crate::vault::use_test_vault().await;
+        let large_result =
+            json!({ "log": "a".repeat(crate::vault::DEFAULT_OFFLOAD_THRESHOLD_BYTES) });
+        let reference =
+            crate::vault::offload_oversized_result(large_result.clone(), large_result.clone(), 0)
+                .await
+                .unwrap()
+                .saved;
+
+        let mock_root_obj: SoclessContext = from_value(json!({
+            "results": { "Fetch_Logs": reference }
+        }))
+        .unwrap();
+
+        let log = resolve_json_path("$.results.Fetch_Logs.log", &mock_root_obj)
+            .await
+            .unwrap();
+        assert_eq!(log, large_result["log"]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_jsonpath_wildcard_does_not_read_vault() {
+        let mock_root_obj: SoclessContext = build_mock_root_obj();
+        let read_vault = AtomicBool::new(false);
+
+        let details = resolve_path("$.artifacts.event.details.*", &mock_root_obj, &read_vault)
+            .await
+            .unwrap();
+        assert!(details
+            .as_array()
+            .unwrap()
+            .contains(&json!("vault:socless_vault_tests.txt")));
+
+        let everything = resolve_path("$..*", &mock_root_obj, &read_vault)
+            .await
+            .unwrap();
+        assert!(everything
+            .as_array()
+            .unwrap()
+            .contains(&json!("vault:socless_vault_tests.txt")));
+        assert!(!read_vault.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_template_rehydrates_offloaded_results() {
+        crate::vault::use_test_vault().await;
+        let large_result =
+            json!({ "log": "a".repeat(crate::vault::DEFAULT_OFFLOAD_THRESHOLD_BYTES) });
+        let reference =
+            crate::vault::offload_oversized_result(large_result.clone(), large_result.clone(), 0)
+                .await
+                .unwrap()
+                .saved;
+
+        let mock_root_obj: SoclessContext = from_value(json!({
+            "results": { "Fetch_Logs": reference }
+        }))
+        .unwrap();
+
+        let result = resolve_reference(
+            &json!({
+                "log": "{{ context.results.Fetch_Logs.log }}",
+                "summary": "{{ context.results.Fetch_Logs.log | length }} bytes of logs"
+            }),
+            &mock_root_obj,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            result,
+            json!({
+                "log": large_result["log"],
+                "summary": format!("{} bytes of logs", crate::vault::DEFAULT_OFFLOAD_THRESHOLD_BYTES)
+            })
+        );
+    }
+
     #[tokio::test]
     async fn test_resolve_reference_string_passthrough() {
         let mock_root_obj: SoclessContext = build_mock_root_obj();
 
-        let result = resolve_reference(&to_value("hello").unwrap(), &mock_root_obj).await;
+        let result = resolve_reference(&to_value("hello").unwrap(), &mock_root_obj)
+            .await
+            .unwrap();
 
         assert_eq!(result, to_value("hello").unwrap());
     }
@@ -409,7 +849,8 @@ mod tests {
             &to_value("$.artifacts.event.details.a_map").unwrap(),
             &mock_root_obj,
         )
-        .await;
+        .await
+        .unwrap();
 
         assert_eq!(
             result,
@@ -425,7 +866,8 @@ mod tests {
             &json!({"firstname": "$.artifacts.event.details.firstname"}),
             &mock_root_obj,
         )
-        .await;
+        .await
+        .unwrap();
 
         assert_eq!(result, json!({"firstname": "Sterling"}));
     }
@@ -434,7 +876,9 @@ mod tests {
     async fn test_resolve_reference_array_passthrough() {
         let mock_root_obj: SoclessContext = build_mock_root_obj();
 
-        let result = resolve_reference(&json!(["test"]), &mock_root_obj).await;
+        let result = resolve_reference(&json!(["test"]), &mock_root_obj)
+            .await
+            .unwrap();
 
         assert_eq!(result, json!(["test"]));
     }
@@ -443,8 +887,59 @@ mod tests {
     async fn test_resolve_reference_jsonpath_nested_array() {
         let mock_root_obj: SoclessContext = build_mock_root_obj();
 
-        let result = resolve_reference(&json!([{"firstname": "$.artifacts.event.details.firstname"}, "$.artifacts.event.details.lastname"]), &mock_root_obj).await;
+        let result = resolve_reference(&json!([{"firstname": "$.artifacts.event.details.firstname"}, "$.artifacts.event.details.lastname"]), &mock_root_obj)
+            .await
+            .unwrap();
 
         assert_eq!(result, json!([{"firstname": "Sterling"}, "Archer"]));
     }
+
+    #[tokio::test]
+    async fn test_resolve_parameters_reading_vault() {
+        crate::vault::use_test_vault().await;
+        let large_result =
+            json!({ "log": "a".repeat(crate::vault::DEFAULT_OFFLOAD_THRESHOLD_BYTES) });
+        let reference =
+            crate::vault::offload_oversized_result(large_result.clone(), large_result, 0)
+                .await
+                .unwrap()
+                .saved;
+        let mut mock_root_obj: SoclessContext = build_mock_root_obj();
+        mock_root_obj.results = Some(from_value(json!({ "Fetch_Logs": reference })).unwrap());
+
+        let params: HashMap<String, Value> = from_value(json!({
+            "username": "$.artifacts.event.details.firstname",
+            "api_key": "vault:socless_vault_tests.txt",
+            "encoded": "vault:socless_vault_tests.txt!base64",
+            "headers": {"Authorization": "vault:socless_vault_tests.json!json"},
+            "nested": "$.artifacts.event.details.vault_test",
+            "log": "$.results.Fetch_Logs.log",
+            "message": "see vault: for details"
+        }))
+        .unwrap();
+
+        let (_, vault_parameters) = resolve_parameters_reading_vault(&params, &mock_root_obj)
+            .await
+            .unwrap();
+        let mut vault_parameters: Vec<String> = vault_parameters.into_iter().collect();
+        vault_parameters.sort();
+        assert_eq!(
+            vault_parameters,
+            ["api_key", "encoded", "headers", "log", "nested"]
+        );
+    }
+
+    #[test]
+    fn test_socless_context_debug_is_redacted() {
+        let context: SoclessContext = from_value(json!({
+            "task_token": "AAAAKgAAAAIAAAAA",
+            "artifacts": {"event": {"details": {"username": "sterling", "password": "guest"}}}
+        }))
+        .unwrap();
+
+        let printed = format!("{:?}", context);
+        assert!(printed.contains("sterling"));
+        assert!(!printed.contains("AAAAKgAAAAIAAAAA"));
+        assert!(!printed.contains("guest"));
+    }
 }
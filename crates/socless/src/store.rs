@@ -0,0 +1,921 @@
+use crate::{
+    config::{get_or_init_config, SoclessConfig, Table},
+    errors::{SoclessError, SoclessResult},
+    utils::{
+        get_item, put_item_in_table, update_item_in_table, ItemUpdate, ReadConsistency,
+        INVALID_DOCUMENT_PATH,
+    },
+    DedupTableItem, EventTableItem, ResponsesTableItem, ResultsTableItem, StateAttempt,
+    StateMetadata,
+};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{from_value, json, to_value, Map, Value};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::sync::OnceCell;
+
+/// Storage backend for the SOCless results, events, dedup and message responses tables.
+///
+/// Every table read and write made by this crate goes through the store returned by
+/// [`get_or_init_store`], which defaults to [`DynamoStore`]. Call [`set_store`] before
+/// any other socless function to swap in a different backend (e.g. for unit tests).
+#[async_trait]
+pub trait SoclessStore: Send + Sync {
+    /// Check that `config` has everything the store needs to use `tables`, so a misconfigured
+    /// function fails before processing an event. Stores that don't use the configured table names
+    /// can keep the default, which accepts any config.
+    fn validate_config(&self, _config: &SoclessConfig, _tables: &[Table]) -> SoclessResult<()> {
+        Ok(())
+    }
+
+    async fn get_execution_results(
+        &self,
+        execution_id: &str,
+    ) -> SoclessResult<Option<ResultsTableItem>>;
+
+    async fn put_execution_results(&self, item: &ResultsTableItem) -> SoclessResult<()>;
+
+    /// Save a State's output to `results.results.<state_name>` and `results.results._Last_Saved_Results`,
+    /// merging `errors` into `results.errors` (errors of other States are kept) and saving
+    /// `metadata` to `results.metadata.<state_name>`. The `results`, `errors` and `metadata` maps
+    /// are created if the item doesn't have them.
+    async fn update_state_results(
+        &self,
+        execution_id: &str,
+        state_name: &str,
+        result: &Value,
+        errors: Option<&HashMap<String, Value>>,
+        metadata: Option<&StateMetadata>,
+    ) -> SoclessResult<()>;
+
+    /// Save a State's failure to `results.errors.<state_name>`, keeping the errors of other States.
+    /// The `errors` map is created if the item doesn't have it.
+    async fn save_state_error(
+        &self,
+        execution_id: &str,
+        state_name: &str,
+        error: &Value,
+    ) -> SoclessResult<()>;
+
+    /// Append an attempt to `results.attempts.<state_name>`, the State's attempt log. The
+    /// `attempts` map is created if the item doesn't have it.
+    async fn append_state_attempt(
+        &self,
+        execution_id: &str,
+        state_name: &str,
+        attempt: &StateAttempt,
+    ) -> SoclessResult<()>;
+
+    async fn get_event(&self, id: &str) -> SoclessResult<Option<EventTableItem>>;
+
+    async fn put_event(&self, item: &EventTableItem) -> SoclessResult<()>;
+
+    async fn get_dedup_mapping(&self, dedup_hash: &str) -> SoclessResult<Option<DedupTableItem>>;
+
+    async fn put_dedup_mapping(&self, item: &DedupTableItem) -> SoclessResult<()>;
+
+    async fn get_message_response(
+        &self,
+        message_id: &str,
+    ) -> SoclessResult<Option<ResponsesTableItem>>;
+
+    async fn put_message_response(&self, item: &ResponsesTableItem) -> SoclessResult<()>;
+
+    /// Mark a message response as fulfilled and save the human's response alongside it.
+    async fn fulfill_message_response(
+        &self,
+        message_id: &str,
+        response_payload: &Value,
+    ) -> SoclessResult<()>;
+}
+
+pub static SOCLESS_STORE: OnceCell<Box<dyn SoclessStore>> = OnceCell::const_new();
+pub async fn get_or_init_store() -> &'static dyn SoclessStore {
+    SOCLESS_STORE
+        .get_or_init(|| async { Box::new(DynamoStore::default()) as Box<dyn SoclessStore> })
+        .await
+        .as_ref()
+}
+
+/// Replace the default [`DynamoStore`] with a custom [`SoclessStore`].
+///
+/// Returns `false` if a store was already initialized, in which case `store` is dropped.
+pub fn set_store(store: impl SoclessStore + 'static) -> bool {
+    SOCLESS_STORE.set(Box::new(store)).is_ok()
+}
+
+/// The default [`SoclessStore`], backed by the DynamoDB tables named in the [`SoclessConfig`]
+/// (by default the `SOCLESS_*_TABLE` environment variables).
+///
+/// Reads are strongly consistent, as they usually follow a write made moments earlier by another
+/// State's Lambda.
+///
+/// [`SoclessConfig`]: crate::config::SoclessConfig
+#[derive(Debug, Default, Clone)]
+pub struct DynamoStore {}
+
+async fn table_name(table: Table) -> SoclessResult<String> {
+    Ok(get_or_init_config().await?.table(table)?.to_owned())
+}
+
+#[async_trait]
+impl SoclessStore for DynamoStore {
+    fn validate_config(&self, config: &SoclessConfig, tables: &[Table]) -> SoclessResult<()> {
+        config.validate_for(tables)
+    }
+
+    async fn get_execution_results(
+        &self,
+        execution_id: &str,
+    ) -> SoclessResult<Option<ResultsTableItem>> {
+        get_item(
+            &table_name(Table::Results).await?,
+            ("execution_id", execution_id),
+            ReadConsistency::Strong,
+        )
+        .await
+    }
+
+    async fn put_execution_results(&self, item: &ResultsTableItem) -> SoclessResult<()> {
+        put_item_in_table(&table_name(Table::Results).await?, item).await?;
+        Ok(())
+    }
+
+    async fn update_state_results(
+        &self,
+        execution_id: &str,
+        state_name: &str,
+        result: &Value,
+        errors: Option<&HashMap<String, Value>>,
+        metadata: Option<&StateMetadata>,
+    ) -> SoclessResult<()> {
+        let mut update = ItemUpdate::new()
+            .set(&["results", "results", state_name], result.clone())
+            .set(
+                &["results", "results", "_Last_Saved_Results"],
+                result.clone(),
+            );
+        for (errored_state, error) in errors.into_iter().flatten() {
+            update = update.set(&["results", "errors", errored_state], error.clone());
+        }
+        let mut maps = vec!["results", "errors"];
+        if let Some(metadata) = metadata {
+            update = update.set(&["results", "metadata", state_name], to_value(metadata)?);
+            maps.push("metadata");
+        }
+        update_results_item(execution_id, &maps, update).await
+    }
+
+    async fn save_state_error(
+        &self,
+        execution_id: &str,
+        state_name: &str,
+        error: &Value,
+    ) -> SoclessResult<()> {
+        let update = ItemUpdate::new().set(&["results", "errors", state_name], error.clone());
+        update_results_item(execution_id, &["errors"], update).await
+    }
+
+    async fn append_state_attempt(
+        &self,
+        execution_id: &str,
+        state_name: &str,
+        attempt: &StateAttempt,
+    ) -> SoclessResult<()> {
+        let update = ItemUpdate::new().append(
+            &["results", "attempts", state_name],
+            vec![to_value(attempt)?],
+        );
+        update_results_item(execution_id, &["attempts"], update).await
+    }
+
+    async fn get_event(&self, id: &str) -> SoclessResult<Option<EventTableItem>> {
+        get_item(
+            &table_name(Table::Events).await?,
+            ("id", id),
+            ReadConsistency::Strong,
+        )
+        .await
+    }
+
+    async fn put_event(&self, item: &EventTableItem) -> SoclessResult<()> {
+        put_item_in_table(&table_name(Table::Events).await?, item).await?;
+        Ok(())
+    }
+
+    async fn get_dedup_mapping(&self, dedup_hash: &str) -> SoclessResult<Option<DedupTableItem>> {
+        get_item(
+            &table_name(Table::Dedup).await?,
+            ("dedup_hash", dedup_hash),
+            ReadConsistency::Strong,
+        )
+        .await
+    }
+
+    async fn put_dedup_mapping(&self, item: &DedupTableItem) -> SoclessResult<()> {
+        put_item_in_table(&table_name(Table::Dedup).await?, item).await?;
+        Ok(())
+    }
+
+    async fn get_message_response(
+        &self,
+        message_id: &str,
+    ) -> SoclessResult<Option<ResponsesTableItem>> {
+        get_item(
+            &table_name(Table::MessageResponse).await?,
+            ("message_id", message_id),
+            ReadConsistency::Strong,
+        )
+        .await
+    }
+
+    async fn put_message_response(&self, item: &ResponsesTableItem) -> SoclessResult<()> {
+        put_item_in_table(&table_name(Table::MessageResponse).await?, item).await?;
+        Ok(())
+    }
+
+    async fn fulfill_message_response(
+        &self,
+        message_id: &str,
+        response_payload: &Value,
+    ) -> SoclessResult<()> {
+        let update = ItemUpdate::new()
+            .set(&["fulfilled"], true)
+            .set(&["response_payload"], response_payload.clone());
+        update_existing_item(Table::MessageResponse, ("message_id", message_id), update).await
+    }
+}
+
+/// Apply `update` to an execution's results item, creating the `results.<map>` maps it writes
+/// into (e.g. `results.errors`) first if the item doesn't have them.
+async fn update_results_item(
+    execution_id: &str,
+    maps: &[&str],
+    update: ItemUpdate,
+) -> SoclessResult<()> {
+    let key = ("execution_id", execution_id);
+    update_creating_maps(maps, update, |update| {
+        update_existing_item(Table::Results, key, update)
+    })
+    .await
+}
+
+/// Apply `update` with `apply`. If it is rejected as an [`SoclessError::InvalidUpdate`], create
+/// the `results` map and its `maps` if they don't exist and apply it again.
+///
+/// Items written by socless always have the `results` and `errors` maps, so maps are only
+/// created (two more writes) after DynamoDB rejects the update. Any other invalid update is
+/// rejected again and that error returned.
+async fn update_creating_maps<F, Fut>(
+    maps: &[&str],
+    update: ItemUpdate,
+    apply: F,
+) -> SoclessResult<()>
+where
+    F: Fn(ItemUpdate) -> Fut,
+    Fut: Future<Output = SoclessResult<()>>,
+{
+    match apply(update.clone()).await {
+        Err(SoclessError::InvalidUpdate(_)) => {
+            // a document path can't be written in the same update as its parent
+            apply(ItemUpdate::new().set_if_not_exists(&["results"], json!({}))).await?;
+
+            let create_maps = maps.iter().fold(ItemUpdate::new(), |create_maps, map| {
+                create_maps.set_if_not_exists(&["results", *map], json!({}))
+            });
+            apply(create_maps).await?;
+
+            apply(update).await
+        }
+        other => other,
+    }
+}
+
+/// Apply `update` to an item, returning [`SoclessError::NotFound`] instead of creating the item
+/// if it doesn't exist.
+async fn update_existing_item(
+    table: Table,
+    (key_name, key_value): (&str, &str),
+    update: ItemUpdate,
+) -> SoclessResult<()> {
+    let update = update.condition_exists(&[key_name]);
+    match update_item_in_table(&table_name(table).await?, (key_name, key_value), &update).await {
+        Ok(_) => Ok(()),
+        Err(SoclessError::ConditionFailed(_)) => Err(SoclessError::NotFound {
+            key: key_value.to_owned(),
+            table: table.to_string(),
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// A thread-safe, in-memory [`SoclessStore`] for hermetic tests and offline development.
+///
+/// Items are kept as JSON documents and `update_state_results` follows the same rules as the
+/// [`DynamoStore`]: the execution must already exist, missing `results.results` and
+/// `results.errors` maps are created and errors are merged.
+/// Clones share the same tables, so a test can keep a handle after passing a clone to [`set_store`].
+/// # Example
+/// ```
+/// use socless::{set_store, MemoryStore};
+///
+/// let store = MemoryStore::default();
+/// set_store(store.clone());
+/// assert!(store.event_items().is_empty());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    tables: Arc<Mutex<MemoryTables>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryTables {
+    results: HashMap<String, Value>,
+    events: HashMap<String, Value>,
+    dedup: HashMap<String, Value>,
+    responses: HashMap<String, Value>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryTables> {
+        // a panic while holding the lock can't leave a table half-written, so ignore poisoning
+        self.tables.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Snapshot of every item in the events table.
+    pub fn event_items(&self) -> Vec<EventTableItem> {
+        self.lock()
+            .events
+            .values()
+            .filter_map(|item| from_value(item.clone()).ok())
+            .collect()
+    }
+
+    /// Snapshot of every item in the results table.
+    pub fn execution_results_items(&self) -> Vec<ResultsTableItem> {
+        self.lock()
+            .results
+            .values()
+            .filter_map(|item| from_value(item.clone()).ok())
+            .collect()
+    }
+}
+
+fn get_memory_item<T: DeserializeOwned>(
+    table: &HashMap<String, Value>,
+    key: &str,
+) -> SoclessResult<Option<T>> {
+    match table.get(key) {
+        Some(item) => Ok(Some(from_value(item.clone())?)),
+        None => Ok(None),
+    }
+}
+
+fn put_memory_item(
+    table: &mut HashMap<String, Value>,
+    key: &str,
+    item: impl Serialize,
+) -> SoclessResult<()> {
+    table.insert(key.to_owned(), to_value(item)?);
+    Ok(())
+}
+
+/// Fails like [`results_map`] would for `map_name`, without creating anything.
+fn check_results_map(
+    results: &HashMap<String, Value>,
+    execution_id: &str,
+    map_name: &str,
+) -> SoclessResult<()> {
+    let item = results
+        .get(execution_id)
+        .and_then(Value::as_object)
+        .ok_or_else(|| SoclessError::NotFound {
+            key: execution_id.to_owned(),
+            table: Table::Results.to_string(),
+        })?;
+    let is_map = match item.get("results") {
+        None => true,
+        Some(playbook_input) => playbook_input.as_object().map_or(false, |maps| {
+            maps.get(map_name).map_or(true, Value::is_object)
+        }),
+    };
+    match is_map {
+        true => Ok(()),
+        false => Err(SoclessError::InvalidUpdate(
+            INVALID_DOCUMENT_PATH.to_owned(),
+        )),
+    }
+}
+
+/// `results.<map_name>` of an execution's results item, created like [`DynamoStore`] does if
+/// the item doesn't have it.
+fn results_map<'a>(
+    results: &'a mut HashMap<String, Value>,
+    execution_id: &str,
+    map_name: &str,
+) -> SoclessResult<&'a mut Map<String, Value>> {
+    let item = results
+        .get_mut(execution_id)
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| SoclessError::NotFound {
+            key: execution_id.to_owned(),
+            table: Table::Results.to_string(),
+        })?;
+    item.entry("results")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .and_then(|playbook_input| {
+            playbook_input
+                .entry(map_name)
+                .or_insert_with(|| json!({}))
+                .as_object_mut()
+        })
+        .ok_or_else(|| SoclessError::InvalidUpdate(INVALID_DOCUMENT_PATH.to_owned()))
+}
+
+#[async_trait]
+impl SoclessStore for MemoryStore {
+    async fn get_execution_results(
+        &self,
+        execution_id: &str,
+    ) -> SoclessResult<Option<ResultsTableItem>> {
+        get_memory_item(&self.lock().results, execution_id)
+    }
+
+    async fn put_execution_results(&self, item: &ResultsTableItem) -> SoclessResult<()> {
+        put_memory_item(&mut self.lock().results, &item.execution_id, item)
+    }
+
+    async fn update_state_results(
+        &self,
+        execution_id: &str,
+        state_name: &str,
+        result: &Value,
+        errors: Option<&HashMap<String, Value>>,
+        metadata: Option<&StateMetadata>,
+    ) -> SoclessResult<()> {
+        let metadata = metadata.map(to_value).transpose()?;
+        let mut tables = self.lock();
+        // check every map before changing any, like DynamoStore's single update expression
+        let map_names = [
+            Some("results"),
+            errors.map(|_| "errors"),
+            metadata.as_ref().map(|_| "metadata"),
+        ];
+        for map_name in map_names.into_iter().flatten() {
+            check_results_map(&tables.results, execution_id, map_name)?;
+        }
+
+        let state_results = results_map(&mut tables.results, execution_id, "results")?;
+        state_results.insert(state_name.to_owned(), result.clone());
+        state_results.insert("_Last_Saved_Results".to_owned(), result.clone());
+
+        if let Some(errors) = errors {
+            results_map(&mut tables.results, execution_id, "errors")?.extend(errors.clone());
+        }
+        if let Some(metadata) = metadata {
+            results_map(&mut tables.results, execution_id, "metadata")?
+                .insert(state_name.to_owned(), metadata);
+        }
+        Ok(())
+    }
+
+    async fn save_state_error(
+        &self,
+        execution_id: &str,
+        state_name: &str,
+        error: &Value,
+    ) -> SoclessResult<()> {
+        let mut tables = self.lock();
+        results_map(&mut tables.results, execution_id, "errors")?
+            .insert(state_name.to_owned(), error.clone());
+        Ok(())
+    }
+
+    async fn append_state_attempt(
+        &self,
+        execution_id: &str,
+        state_name: &str,
+        attempt: &StateAttempt,
+    ) -> SoclessResult<()> {
+        let attempt = to_value(attempt)?;
+        let mut tables = self.lock();
+        results_map(&mut tables.results, execution_id, "attempts")?
+            .entry(state_name)
+            .or_insert_with(|| json!([]))
+            .as_array_mut()
+            .ok_or_else(|| {
+                SoclessError::Storage(
+                    "An operand in the update expression has an incorrect data type".to_owned(),
+                )
+            })?
+            .push(attempt);
+        Ok(())
+    }
+
+    async fn get_event(&self, id: &str) -> SoclessResult<Option<EventTableItem>> {
+        get_memory_item(&self.lock().events, id)
+    }
+
+    async fn put_event(&self, item: &EventTableItem) -> SoclessResult<()> {
+        put_memory_item(&mut self.lock().events, &item.id, item)
+    }
+
+    async fn get_dedup_mapping(&self, dedup_hash: &str) -> SoclessResult<Option<DedupTableItem>> {
+        get_memory_item(&self.lock().dedup, dedup_hash)
+    }
+
+    async fn put_dedup_mapping(&self, item: &DedupTableItem) -> SoclessResult<()> {
+        put_memory_item(&mut self.lock().dedup, &item.dedup_hash, item)
+    }
+
+    async fn get_message_response(
+        &self,
+        message_id: &str,
+    ) -> SoclessResult<Option<ResponsesTableItem>> {
+        get_memory_item(&self.lock().responses, message_id)
+    }
+
+    async fn put_message_response(&self, item: &ResponsesTableItem) -> SoclessResult<()> {
+        put_memory_item(&mut self.lock().responses, &item.message_id, item)
+    }
+
+    async fn fulfill_message_response(
+        &self,
+        message_id: &str,
+        response_payload: &Value,
+    ) -> SoclessResult<()> {
+        let mut tables = self.lock();
+        let item = tables
+            .responses
+            .get_mut(message_id)
+            .ok_or_else(|| SoclessError::NotFound {
+                key: message_id.to_owned(),
+                table: Table::MessageResponse.to_string(),
+            })?;
+        item["fulfilled"] = Value::Bool(true);
+        item["response_payload"] = response_payload.clone();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::hashmap;
+    use serde_json::json;
+
+    #[test]
+    fn test_only_dynamo_store_needs_configured_tables() {
+        let config = SoclessConfig::builder()
+            .results_table("results")
+            .build()
+            .unwrap();
+
+        let dynamo = DynamoStore::default();
+        assert!(dynamo.validate_config(&config, &[Table::Results]).is_ok());
+        assert!(matches!(
+            dynamo.validate_config(&config, &[Table::Results, Table::Events]),
+            Err(SoclessError::Config(_))
+        ));
+        assert!(MemoryStore::new()
+            .validate_config(&SoclessConfig::default(), &[Table::Events])
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_update_creating_maps_after_an_invalid_update() {
+        let applied = Mutex::new(vec![]);
+        let apply = |update: ItemUpdate| {
+            let mut applied = applied.lock().unwrap();
+            applied.push(update);
+            let result = match applied.len() {
+                1 => Err(SoclessError::InvalidUpdate(
+                    INVALID_DOCUMENT_PATH.to_owned(),
+                )),
+                _ => Ok(()),
+            };
+            async move { result }
+        };
+        let update = ItemUpdate::new().set(&["results", "errors", "Get_User"], json!({}));
+
+        update_creating_maps(&["results", "errors"], update.clone(), apply)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            applied.into_inner().unwrap(),
+            vec![
+                update.clone(),
+                ItemUpdate::new().set_if_not_exists(&["results"], json!({})),
+                ItemUpdate::new()
+                    .set_if_not_exists(&["results", "results"], json!({}))
+                    .set_if_not_exists(&["results", "errors"], json!({})),
+                update,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_creating_maps_only_retries_invalid_updates() {
+        let applied = Mutex::new(0);
+        let apply = |_: ItemUpdate| {
+            *applied.lock().unwrap() += 1;
+            async { Err(SoclessError::Storage("throttled".to_owned())) }
+        };
+
+        let result = update_creating_maps(&["errors"], ItemUpdate::new(), apply).await;
+
+        assert!(matches!(result, Err(SoclessError::Storage(_))));
+        assert_eq!(applied.into_inner().unwrap(), 1);
+    }
+
+    fn mock_results_item(execution_id: &str) -> ResultsTableItem {
+        ResultsTableItem {
+            execution_id: execution_id.to_owned(),
+            investigation_id: "1234-45678-abcd".to_owned(),
+            datetime: "2021-01-16T00:57:06.573112Z".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_update_state_results() {
+        let store = MemoryStore::new();
+        store
+            .put_execution_results(&mock_results_item("exec-1"))
+            .await
+            .unwrap();
+
+        store
+            .update_state_results(
+                "exec-1",
+                "Get_User",
+                &json!({"user": "sterling"}),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        store
+            .update_state_results(
+                "exec-1",
+                "Get_Manager",
+                &json!({"user": "malory"}),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let item = store
+            .get_execution_results("exec-1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            item.results.results["Get_User"],
+            json!({"user": "sterling"})
+        );
+        assert_eq!(
+            item.results.results["Get_Manager"],
+            json!({"user": "malory"})
+        );
+        assert_eq!(
+            item.results.results["_Last_Saved_Results"],
+            json!({"user": "malory"})
+        );
+        assert!(item.results.errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_update_state_results_merges_errors() {
+        let store = MemoryStore::new();
+        store
+            .put_execution_results(&mock_results_item("exec-2"))
+            .await
+            .unwrap();
+        store
+            .save_state_error("exec-2", "Get_Manager", &json!({"error": "not found"}))
+            .await
+            .unwrap();
+
+        let errors: HashMap<String, Value> =
+            from_value(json!({"Get_User": {"error": "timed out"}})).unwrap();
+        store
+            .update_state_results("exec-2", "Get_User", &json!({}), Some(&errors), None)
+            .await
+            .unwrap();
+
+        let item = store
+            .get_execution_results("exec-2")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            item.results.errors["Get_User"],
+            json!({"error": "timed out"})
+        );
+        assert_eq!(
+            item.results.errors["Get_Manager"],
+            json!({"error": "not found"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_update_state_results_creates_missing_maps() {
+        let store = MemoryStore::new();
+        store
+            .lock()
+            .results
+            .insert("exec-4".to_owned(), json!({"execution_id": "exec-4"}));
+
+        store
+            .update_state_results(
+                "exec-4",
+                "Get_User",
+                &json!({"user": "sterling"}),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        store
+            .save_state_error("exec-4", "Get_Manager", &json!({"error": "not found"}))
+            .await
+            .unwrap();
+
+        let item = store.lock().results["exec-4"].clone();
+        assert_eq!(
+            item["results"]["results"]["Get_User"],
+            json!({"user": "sterling"})
+        );
+        assert_eq!(
+            item["results"]["errors"]["Get_Manager"],
+            json!({"error": "not found"})
+        );
+
+        store.lock().results.insert(
+            "exec-5".to_owned(),
+            json!({"execution_id": "exec-5", "results": "not a map"}),
+        );
+        let invalid = store
+            .save_state_error("exec-5", "Get_User", &json!({}))
+            .await;
+        assert!(matches!(invalid, Err(SoclessError::InvalidUpdate(_))));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_update_state_results_is_all_or_nothing() {
+        let store = MemoryStore::new();
+        let item = json!({
+            "execution_id": "exec-6",
+            "results": {"results": {}, "errors": {}, "metadata": "not a map"}
+        });
+        store
+            .lock()
+            .results
+            .insert("exec-6".to_owned(), item.clone());
+
+        let errors = hashmap! {"Get_Manager".to_owned() => json!({"error": "not found"})};
+        let invalid = store
+            .update_state_results(
+                "exec-6",
+                "Get_User",
+                &json!({"user": "sterling"}),
+                Some(&errors),
+                Some(&StateMetadata::default()),
+            )
+            .await;
+        assert!(matches!(invalid, Err(SoclessError::InvalidUpdate(_))));
+        assert_eq!(store.lock().results["exec-6"], item);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_save_state_error_keeps_other_errors() {
+        let store = MemoryStore::new();
+        store
+            .put_execution_results(&mock_results_item("exec-3"))
+            .await
+            .unwrap();
+
+        store
+            .save_state_error("exec-3", "Get_User", &json!({"message": "timed out"}))
+            .await
+            .unwrap();
+        store
+            .save_state_error("exec-3", "Get_Manager", &json!({"message": "not found"}))
+            .await
+            .unwrap();
+
+        let item = store
+            .get_execution_results("exec-3")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            item.results.errors["Get_User"],
+            json!({"message": "timed out"})
+        );
+        assert_eq!(
+            item.results.errors["Get_Manager"],
+            json!({"message": "not found"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_append_state_attempt() {
+        let store = MemoryStore::new();
+        store
+            .put_execution_results(&mock_results_item("exec-6"))
+            .await
+            .unwrap();
+
+        for attempt in 1..=2 {
+            store
+                .append_state_attempt(
+                    "exec-6",
+                    "Get_User",
+                    &StateAttempt {
+                        attempt,
+                        request_id: format!("request-{}", attempt),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        let item = store
+            .get_execution_results("exec-6")
+            .await
+            .unwrap()
+            .unwrap();
+        let request_ids: Vec<_> = item.results.attempts["Get_User"]
+            .iter()
+            .map(|attempt| attempt.request_id.as_str())
+            .collect();
+        assert_eq!(request_ids, ["request-1", "request-2"]);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_update_state_results_missing_execution() {
+        let store = MemoryStore::new();
+
+        let result = store
+            .update_state_results("does-not-exist", "Get_User", &json!({}), None, None)
+            .await;
+
+        assert!(matches!(result, Err(SoclessError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_clones_share_tables() {
+        let store = MemoryStore::new();
+        let handle = store.clone();
+
+        store
+            .put_event(&EventTableItem {
+                id: "event-1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(handle.event_items().len(), 1);
+        assert!(handle.get_event("event-1").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_fulfill_message_response() {
+        let store = MemoryStore::new();
+        store
+            .put_message_response(&ResponsesTableItem {
+                message_id: "msg-1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        store
+            .fulfill_message_response("msg-1", &json!({"approved": true}))
+            .await
+            .unwrap();
+
+        let response = store.get_message_response("msg-1").await.unwrap().unwrap();
+        assert!(response.fulfilled);
+
+        let missing = store
+            .fulfill_message_response("msg-2", &json!({"approved": true}))
+            .await;
+        assert!(matches!(missing, Err(SoclessError::NotFound { .. })));
+    }
+}
@@ -0,0 +1,581 @@
+//! Jinja-style `{{ }}` templates in State parameters.
+//!
+//! Templates are rendered against `{"context": <SoclessContext>}`, so
+//! `{{ context.artifacts.event.details.user }}` reads the same value as
+//! `$.artifacts.event.details.user`. Supported syntax:
+//! - variables with names, indexes and quoted keys: `{{ context.results.Lookup.items[0]['first name'] }}`
+//! - filters, chained with `|`: `lower`, `upper`, `trim`, `length`, `tojson` and `default(value)` (or `d`)
+//! - string and number literals: `{{ 'n/a' }}`
+//!
+//! A parameter that is a single `{{ expression }}` resolves to the expression's json value,
+//! anything else is rendered to a string with non-string values written as json.
+//!
+//! When rendered by the parameter resolver, `vault:` references met along a variable's lookups
+//! are replaced by the vault content, the same as in `$.` references, so
+//! `{{ context.results.<State>.field }}` still works once a State's result is offloaded.
+
+use crate::errors::{SoclessError, SoclessResult};
+use serde_json::Value;
+use std::{borrow::Cow, future::Future};
+
+const TEMPLATE_START: &str = "{{";
+const TEMPLATE_END: &str = "}}";
+
+/// Whether a parameter string contains a `{{ expression }}` to render.
+pub fn is_template(reference: &str) -> bool {
+    match reference.find(TEMPLATE_START) {
+        Some(start) => reference[start..].contains(TEMPLATE_END),
+        None => false,
+    }
+}
+
+/// Render a template against `root`, see the [module docs](self) for the syntax.
+/// ### Example
+/// ```
+/// # use serde_json::json;
+/// # use socless::template::render;
+/// let root = json!({"context": {"artifacts": {"event": {"details": {"user": "Sterling", "age": 35}}}}});
+///
+/// let message = render("User {{ context.artifacts.event.details.user | upper }} logged in", &root).unwrap();
+/// assert_eq!(message, json!("User STERLING logged in"));
+///
+/// let age = render("{{ context.artifacts.event.details.age }}", &root).unwrap();
+/// assert_eq!(age, json!(35));
+/// ```
+pub fn render(template: &str, root: &Value) -> SoclessResult<Value> {
+    let parts = parse_template(template)?;
+
+    if let [Part::Expression(source)] = parts.as_slice() {
+        return evaluate(source, root);
+    }
+
+    let mut rendered = String::new();
+    for part in parts {
+        match part {
+            Part::Text(text) => rendered.push_str(text),
+            Part::Expression(source) => rendered.push_str(&to_text(&evaluate(source, root)?)),
+        }
+    }
+    Ok(Value::String(rendered))
+}
+
+/// [`render`], passing every string met along a variable's lookups through `rehydrate` before
+/// looking further into it, e.g. to replace `vault:` references with the vault content.
+pub(crate) async fn render_with<F, Fut>(
+    template: &str,
+    root: &Value,
+    rehydrate: F,
+) -> SoclessResult<Value>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = SoclessResult<Value>>,
+{
+    let parts = parse_template(template)?;
+
+    if let [Part::Expression(source)] = parts.as_slice() {
+        return evaluate_with(source, root, &rehydrate).await;
+    }
+
+    let mut rendered = String::new();
+    for part in parts {
+        match part {
+            Part::Text(text) => rendered.push_str(text),
+            Part::Expression(source) => {
+                rendered.push_str(&to_text(&evaluate_with(source, root, &rehydrate).await?))
+            }
+        }
+    }
+    Ok(Value::String(rendered))
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Expression(&'a str),
+}
+
+fn parse_template(template: &str) -> SoclessResult<Vec<Part<'_>>> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find(TEMPLATE_START) {
+        if start > 0 {
+            parts.push(Part::Text(&rest[..start]));
+        }
+        let expression = &rest[start + TEMPLATE_START.len()..];
+        let end = find_expression_end(expression).ok_or_else(|| {
+            SoclessError::Resolution(format!("unclosed '{{{{' in template {}", template))
+        })?;
+        parts.push(Part::Expression(&expression[..end]));
+        rest = &expression[end + TEMPLATE_END.len()..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    Ok(parts)
+}
+
+/// Index of the `}}` closing an expression, ignoring any inside quoted strings.
+fn find_expression_end(expression: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut chars = expression.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            Some(_) if c == '\\' => {
+                chars.next();
+            }
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if expression[i..].starts_with(TEMPLATE_END) => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+/// A value, or `None` when a variable doesn't exist.
+type Lookup = Option<Value>;
+
+/// The start of an expression, before any filters.
+enum Base {
+    Literal(Value),
+    /// A variable followed by `.name`, `[index]` and `['key']` lookups.
+    Variable(String, Vec<Key>),
+}
+
+enum Key {
+    /// `.name`, which also indexes arrays when it's a number.
+    Name(String),
+    /// `[index]` or `['key']`
+    Bracket(Value),
+}
+
+/// A parsed `{{ expression }}`.
+struct Expression<'a> {
+    parser: Parser<'a>,
+    base: Base,
+    filters: Vec<(String, Vec<Value>)>,
+}
+
+impl<'a> Expression<'a> {
+    fn parse(source: &'a str) -> SoclessResult<Self> {
+        let mut parser = Parser::new(source);
+        let base = parser.base()?;
+        parser.skip_whitespace();
+        let mut filters = vec![];
+        while parser.eat('|') {
+            filters.push(parser.filter_call()?);
+            parser.skip_whitespace();
+        }
+        if !parser.at_end() {
+            return Err(parser.error("unexpected character"));
+        }
+        Ok(Expression {
+            parser,
+            base,
+            filters,
+        })
+    }
+
+    /// Apply the filters to the looked up `value`.
+    fn finish(self, mut value: Lookup) -> SoclessResult<Value> {
+        for (filter, args) in &self.filters {
+            value = apply_filter(filter, args, value).map_err(|reason| {
+                self.parser
+                    .error(&format!("filter '{}' {}", filter, reason))
+            })?;
+        }
+        value.ok_or_else(|| {
+            self.parser
+                .error("undefined variable, use the 'default' filter if it's optional")
+        })
+    }
+}
+
+fn evaluate(source: &str, root: &Value) -> SoclessResult<Value> {
+    let expression = Expression::parse(source)?;
+    let value = match &expression.base {
+        Base::Literal(literal) => Some(literal.to_owned()),
+        Base::Variable(variable, keys) => root
+            .get(variable)
+            .and_then(|value| keys.iter().try_fold(value, lookup))
+            .cloned(),
+    };
+    expression.finish(value)
+}
+
+async fn evaluate_with<F, Fut>(source: &str, root: &Value, rehydrate: &F) -> SoclessResult<Value>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = SoclessResult<Value>>,
+{
+    let expression = Expression::parse(source)?;
+    let value = match &expression.base {
+        Base::Literal(literal) => Some(literal.to_owned()),
+        Base::Variable(variable, keys) => lookup_path(root, variable, keys, rehydrate).await?,
+    };
+    expression.finish(value)
+}
+
+/// Follow `keys` from `root[variable]`, passing every string met along the way through
+/// `rehydrate`.
+async fn lookup_path<F, Fut>(
+    root: &Value,
+    variable: &str,
+    keys: &[Key],
+    rehydrate: &F,
+) -> SoclessResult<Lookup>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = SoclessResult<Value>>,
+{
+    let mut value = root.get(variable).map(Cow::Borrowed);
+    let mut keys = keys.iter();
+    loop {
+        if let Some(Value::String(string)) = value.as_deref() {
+            let string = string.to_owned();
+            value = Some(Cow::Owned(rehydrate(string).await?));
+        }
+        value = match (value, keys.next()) {
+            (Some(Cow::Borrowed(current)), Some(key)) => lookup(current, key).map(Cow::Borrowed),
+            (Some(Cow::Owned(current)), Some(key)) => {
+                lookup(&current, key).cloned().map(Cow::Owned)
+            }
+            (current, _) => return Ok(current.map(Cow::into_owned)),
+        };
+    }
+}
+
+fn lookup<'v>(value: &'v Value, key: &Key) -> Option<&'v Value> {
+    match (key, value) {
+        (Key::Name(name), Value::Array(array)) => {
+            name.parse().ok().and_then(|i: usize| array.get(i))
+        }
+        (Key::Name(name), other) => other.get(name),
+        (Key::Bracket(Value::Number(index)), Value::Array(array)) => index
+            .as_i64()
+            .map(|i| if i < 0 { array.len() as i64 + i } else { i })
+            .and_then(|i| usize::try_from(i).ok())
+            .and_then(|i| array.get(i)),
+        (Key::Bracket(Value::String(key)), other) => other.get(key),
+        _ => None,
+    }
+}
+
+fn apply_filter(filter: &str, args: &[Value], value: Lookup) -> Result<Lookup, String> {
+    if filter == "default" || filter == "d" {
+        return Ok(match value {
+            Some(value) if !value.is_null() => Some(value),
+            _ => Some(
+                args.first()
+                    .cloned()
+                    .unwrap_or_else(|| Value::String(String::new())),
+            ),
+        });
+    }
+
+    let value = value.ok_or_else(|| "applied to an undefined variable".to_owned())?;
+    let filtered = match filter {
+        "lower" => Value::String(to_text(&value).to_lowercase()),
+        "upper" => Value::String(to_text(&value).to_uppercase()),
+        "trim" => Value::String(to_text(&value).trim().to_owned()),
+        "tojson" => Value::String(value.to_string()),
+        "length" => Value::from(match &value {
+            Value::String(string) => string.chars().count(),
+            Value::Array(array) => array.len(),
+            Value::Object(map) => map.len(),
+            _ => return Err("needs a string, array or map".to_owned()),
+        }),
+        _ => return Err("does not exist".to_owned()),
+    };
+    Ok(Some(filtered))
+}
+
+/// Strings as is, anything else as json.
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.to_owned(),
+        other => other.to_string(),
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Parser {
+            source,
+            chars: source.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn error(&self, reason: &str) -> SoclessError {
+        SoclessError::Resolution(format!(
+            "unable to render {{{{{}}}}}: {}",
+            self.source, reason
+        ))
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> SoclessResult<()> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn identifier(&mut self) -> SoclessResult<String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .map_or(false, |c| c.is_alphanumeric() || c == '_' || c == '-')
+        {
+            self.pos += 1;
+        }
+        match self.pos > start {
+            true => Ok(self.chars[start..self.pos].iter().collect()),
+            false => Err(self.error("expected a name")),
+        }
+    }
+
+    /// A literal, or a variable followed by `.name`, `[index]` and `['key']` lookups.
+    fn base(&mut self) -> SoclessResult<Base> {
+        self.skip_whitespace();
+        if !self.peek().map_or(false, |c| c.is_alphabetic() || c == '_') {
+            return self.literal().map(Base::Literal);
+        }
+
+        let variable = self.identifier()?;
+        let mut keys = vec![];
+        loop {
+            if self.eat('.') {
+                keys.push(Key::Name(self.identifier()?));
+            } else if self.eat('[') {
+                self.skip_whitespace();
+                keys.push(Key::Bracket(self.literal()?));
+                self.skip_whitespace();
+                self.expect(']')?;
+            } else {
+                return Ok(Base::Variable(variable, keys));
+            }
+        }
+    }
+
+    /// `name` or `name(arg, ...)`
+    fn filter_call(&mut self) -> SoclessResult<(String, Vec<Value>)> {
+        self.skip_whitespace();
+        let name = self.identifier()?;
+        self.skip_whitespace();
+        let mut args = vec![];
+        if self.eat('(') {
+            self.skip_whitespace();
+            if !self.eat(')') {
+                loop {
+                    self.skip_whitespace();
+                    args.push(self.literal()?);
+                    self.skip_whitespace();
+                    if self.eat(')') {
+                        break;
+                    }
+                    self.expect(',')?;
+                }
+            }
+        }
+        Ok((name, args))
+    }
+
+    /// A quoted string, number, `true`, `false` or `null`.
+    fn literal(&mut self) -> SoclessResult<Value> {
+        match self.peek() {
+            Some(quote) if quote == '\'' || quote == '"' => {
+                self.pos += 1;
+                let mut string = String::new();
+                loop {
+                    match self.peek() {
+                        None => return Err(self.error("unterminated string")),
+                        Some('\\') => {
+                            self.pos += 1;
+                            string.extend(self.peek());
+                        }
+                        Some(c) if c == quote => {
+                            self.pos += 1;
+                            return Ok(Value::String(string));
+                        }
+                        Some(c) => string.push(c),
+                    }
+                    self.pos += 1;
+                }
+            }
+            _ => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .map_or(false, |c| c.is_ascii_alphanumeric() || "-+.".contains(c))
+                {
+                    self.pos += 1;
+                }
+                let literal: String = self.chars[start..self.pos].iter().collect();
+                serde_json::from_str(&literal).map_err(|_| self.error("expected a literal"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn mock_root() -> Value {
+        json!({
+            "context": {
+                "artifacts": {
+                    "event": {
+                        "details": {
+                            "user": "Sterling",
+                            "age": 35,
+                            "items": ["camera", {"pin": 1234}],
+                            "first name": "  Sterling  "
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_is_template() {
+        assert!(is_template("{{ context.artifacts }}"));
+        assert!(is_template("Hello {{context.user}}!"));
+        assert!(!is_template("$.artifacts.event"));
+        assert!(!is_template("}} not a template {{"));
+    }
+
+    #[test]
+    fn test_single_expression_keeps_json_type() {
+        let root = mock_root();
+        assert_eq!(
+            render("{{context.artifacts.event.details.age}}", &root).unwrap(),
+            json!(35)
+        );
+        assert_eq!(
+            render("{{ context.artifacts.event.details.items[1] }}", &root).unwrap(),
+            json!({"pin": 1234})
+        );
+        assert_eq!(
+            render("{{ context.artifacts.event.details.items.1.pin }}", &root).unwrap(),
+            json!(1234)
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let root = mock_root();
+        assert_eq!(
+            render(
+                "User {{context.artifacts.event.details.user}} ({{ context.artifacts.event.details.age }}) has {{ context.artifacts.event.details.items | length }} items",
+                &root
+            )
+            .unwrap(),
+            json!("User Sterling (35) has 2 items")
+        );
+        assert_eq!(
+            render(
+                "pin: {{ context.artifacts.event.details.items[-1] }}",
+                &root
+            )
+            .unwrap(),
+            json!("pin: {\"pin\":1234}")
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let root = mock_root();
+        let details = "context.artifacts.event.details";
+        let cases = [
+            (
+                format!("{{{{ {}.user | lower }}}}", details),
+                json!("sterling"),
+            ),
+            (
+                format!("{{{{ {}.user|upper }}}}", details),
+                json!("STERLING"),
+            ),
+            (
+                format!("{{{{ {}['first name'] | trim }}}}", details),
+                json!("Sterling"),
+            ),
+            (
+                format!("{{{{ {}.items | tojson }}}}", details),
+                json!("[\"camera\",{\"pin\":1234}]"),
+            ),
+            (
+                format!("{{{{ {}.missing | default('n/a') }}}}", details),
+                json!("n/a"),
+            ),
+            (format!("{{{{ {}.missing | d(0) }}}}", details), json!(0)),
+            (
+                format!("{{{{ {}.user | default('n/a') | lower }}}}", details),
+                json!("sterling"),
+            ),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(render(&template, &root).unwrap(), expected, "{}", template);
+        }
+    }
+
+    #[test]
+    fn test_quoted_braces_in_arguments() {
+        let root = mock_root();
+        assert_eq!(
+            render("{{ context.missing | default('}}') }}", &root).unwrap(),
+            json!("}}")
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let root = mock_root();
+        for template in [
+            "{{ context.artifacts.event.details.missing }}",
+            "Hello {{ context.artifacts.event.details.missing | upper }}",
+            "{{ context.artifacts.event.details.user | not_a_filter }}",
+            "{{ context.artifacts.event.details.user",
+            "{{ context.artifacts.event.details.age | length }}",
+        ] {
+            assert!(
+                matches!(render(template, &root), Err(SoclessError::Resolution(_))),
+                "{} should not render",
+                template
+            );
+        }
+    }
+}
@@ -1,8 +1,16 @@
 use crate::clients::get_or_init_dynamo;
-use aws_sdk_dynamodb::{error::PutItemError, model::AttributeValue, output::PutItemOutput};
+use crate::errors::{SoclessError, SoclessResult};
+use aws_sdk_dynamodb::{
+    model::AttributeValue,
+    output::{PutItemOutput, UpdateItemOutput},
+    SdkError,
+};
 use chrono::Utc;
-use serde_dynamo::{to_attribute_value, to_item};
+use serde::de::DeserializeOwned;
+use serde_dynamo::{from_item, to_attribute_value, to_item};
+use serde_json::Value;
 use std::collections::HashMap;
+use tracing::instrument;
 use uuid::Uuid;
 
 /// Generate current timestamp in ISO8601 UTC format
@@ -25,67 +33,393 @@ pub fn gen_id() -> String {
     Uuid::new_v4().to_string()
 }
 
+/// Read consistency of [`get_item`], see
+/// <https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/HowItWorks.ReadConsistency.html>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadConsistency {
+    /// May not reflect a write made in the last second, at half the cost.
+    Eventual,
+    /// Reflects every write that succeeded before the read.
+    Strong,
+}
+
+impl Default for ReadConsistency {
+    fn default() -> Self {
+        ReadConsistency::Eventual
+    }
+}
+
+/// Get the item whose primary key `key.0` has the value `key.1`, deserialized into `T`.
+///
+/// Returns `Ok(None)` if there is no such item, a [`SoclessError::Storage`] if the request failed,
+/// and a [`SoclessError::Serialization`] if the item doesn't match `T`.
+/// ## Example
+/// ```ignore
+/// let results: Option<ResultsTableItem> =
+///     get_item(&results_table_name, ("execution_id", &execution_id), ReadConsistency::Strong).await?;
+/// ```
+pub async fn get_item<T: DeserializeOwned>(
+    table_name: &str,
+    key: (&str, &str),
+    consistency: ReadConsistency,
+) -> SoclessResult<Option<T>> {
+    match get_raw_item(table_name, key, consistency).await? {
+        Some(item) => Ok(Some(from_item(item)?)),
+        None => Ok(None),
+    }
+}
+
+/// Same as [`get_item`] with [`ReadConsistency::Eventual`], returning the item as DynamoDB
+/// attribute values.
 pub async fn get_item_from_table(
     primary_key_name: &str,
     primary_key_value: &str,
     table_name: &str,
-) -> Option<HashMap<String, AttributeValue>> {
-    let client = get_or_init_dynamo().await;
+) -> SoclessResult<Option<HashMap<String, AttributeValue>>> {
+    get_raw_item(
+        table_name,
+        (primary_key_name, primary_key_value),
+        ReadConsistency::Eventual,
+    )
+    .await
+}
 
-    let result = client
+#[instrument(level = "debug", skip_all, fields(table = table_name, key = primary_key_value))]
+async fn get_raw_item(
+    table_name: &str,
+    (primary_key_name, primary_key_value): (&str, &str),
+    consistency: ReadConsistency,
+) -> SoclessResult<Option<HashMap<String, AttributeValue>>> {
+    let result = get_or_init_dynamo()
+        .await?
         .get_item()
-        .key(
-            primary_key_name,
-            to_attribute_value(primary_key_value).unwrap(),
-        )
+        .table_name(table_name)
+        .key(primary_key_name, to_attribute_value(primary_key_value)?)
+        .consistent_read(consistency == ReadConsistency::Strong)
         .send()
         .await
-        .unwrap_or_else(|_| {
-            panic!(
-                "Error in get_item of table: {} for key= {{ {} : {} }}",
-                table_name, primary_key_name, primary_key_value
-            )
-        });
+        .map_err(|e| {
+            SoclessError::Storage(format!(
+                "Error in get_item of table: {} for key= {{ {} : {} }}: {}",
+                table_name, primary_key_name, primary_key_value, e
+            ))
+        })?;
 
-    result.item
+    Ok(result.item)
 }
 
 /// ## Example
 /// ```ignore
 /// put_item_in_table(&results_table_name, &results_table_input)
-/// .await
-/// .expect("failed to store item");
+/// .await?;
 /// ```
+#[instrument(level = "debug", skip_all, fields(table = table_name))]
 pub async fn put_item_in_table(
     table_name: &str,
     table_item: impl serde::ser::Serialize,
-) -> Result<PutItemOutput, aws_sdk_dynamodb::SdkError<PutItemError>> {
+) -> SoclessResult<PutItemOutput> {
     get_or_init_dynamo()
-        .await
+        .await?
         .put_item()
         .table_name(table_name)
-        .set_item(Some(to_item(table_item).unwrap()))
+        .set_item(Some(to_item(table_item)?))
         .send()
         .await
+        .map_err(|e| {
+            SoclessError::Storage(format!("Error in put_item of table: {}: {}", table_name, e))
+        })
+}
+
+/// A partial update of a DynamoDB item, built into an `UpdateExpression` (and optional
+/// `ConditionExpression`) by [`ItemUpdate::build`] and sent by [`update_item_in_table`].
+///
+/// Paths are lists of map keys, each replaced by an attribute-name placeholder, so any key is
+/// safe to use (reserved words, dots, spaces).
+/// ## Example
+/// ```
+/// use serde_json::json;
+/// use socless::utils::ItemUpdate;
+///
+/// let update = ItemUpdate::new()
+///     .set(&["fulfilled"], true)
+///     .set(&["response_payload"], json!({"answer": "yes"}))
+///     .condition_exists(&["message_id"])
+///     .build();
+/// assert_eq!(
+///     update.update_expression,
+///     "SET #n0 = :v0, #n1 = :v1"
+/// );
+/// assert_eq!(update.condition_expression.as_deref(), Some("attribute_exists(#n2)"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ItemUpdate {
+    actions: Vec<UpdateAction>,
+    conditions: Vec<UpdateCondition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum UpdateAction {
+    Set(Vec<String>, Value),
+    SetIfNotExists(Vec<String>, Value),
+    Append(Vec<String>, Vec<Value>),
+    Remove(Vec<String>),
 }
 
+#[derive(Debug, Clone, PartialEq)]
+enum UpdateCondition {
+    Exists(Vec<String>),
+    NotExists(Vec<String>),
+    Equals(Vec<String>, Value),
+}
+
+/// The expressions of an [`ItemUpdate`], with their attribute names and values.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpdateExpression {
+    pub update_expression: String,
+    pub condition_expression: Option<String>,
+    pub attribute_names: HashMap<String, String>,
+    pub attribute_values: HashMap<String, Value>,
+}
+
+fn owned_path(path: &[&str]) -> Vec<String> {
+    path.iter().map(|key| key.to_string()).collect()
+}
+
+impl ItemUpdate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `SET path = value`. The map containing `path` must already exist.
+    pub fn set(mut self, path: &[&str], value: impl Into<Value>) -> Self {
+        self.actions
+            .push(UpdateAction::Set(owned_path(path), value.into()));
+        self
+    }
+
+    /// Set `path` to `value` only if it has no value yet, e.g. to create a map before setting
+    /// keys inside it in a later update.
+    pub fn set_if_not_exists(mut self, path: &[&str], value: impl Into<Value>) -> Self {
+        self.actions
+            .push(UpdateAction::SetIfNotExists(owned_path(path), value.into()));
+        self
+    }
+
+    /// Append `values` to the list at `path`, creating the list if it doesn't exist.
+    pub fn append(mut self, path: &[&str], values: Vec<Value>) -> Self {
+        self.actions
+            .push(UpdateAction::Append(owned_path(path), values));
+        self
+    }
+
+    /// `REMOVE path`.
+    pub fn remove(mut self, path: &[&str]) -> Self {
+        self.actions.push(UpdateAction::Remove(owned_path(path)));
+        self
+    }
+
+    /// Only update the item if `path` exists, e.g. the item's primary key to not create new items.
+    pub fn condition_exists(mut self, path: &[&str]) -> Self {
+        self.conditions
+            .push(UpdateCondition::Exists(owned_path(path)));
+        self
+    }
+
+    /// Only update the item if `path` doesn't exist.
+    pub fn condition_not_exists(mut self, path: &[&str]) -> Self {
+        self.conditions
+            .push(UpdateCondition::NotExists(owned_path(path)));
+        self
+    }
+
+    /// Only update the item if `path` equals `value`.
+    pub fn condition_equals(mut self, path: &[&str], value: impl Into<Value>) -> Self {
+        self.conditions
+            .push(UpdateCondition::Equals(owned_path(path), value.into()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    pub fn build(&self) -> UpdateExpression {
+        let mut expression = UpdateExpression::default();
+        let mut set_clauses = vec![];
+        let mut remove_clauses = vec![];
+
+        for action in &self.actions {
+            match action {
+                UpdateAction::Set(path, value) => {
+                    let path = expression.name_path(path);
+                    let value = expression.value(value.clone());
+                    set_clauses.push(format!("{} = {}", path, value));
+                }
+                UpdateAction::SetIfNotExists(path, value) => {
+                    let path = expression.name_path(path);
+                    let value = expression.value(value.clone());
+                    set_clauses.push(format!("{0} = if_not_exists({0}, {1})", path, value));
+                }
+                UpdateAction::Append(path, values) => {
+                    let path = expression.name_path(path);
+                    let empty = expression.value(Value::Array(vec![]));
+                    let values = expression.value(Value::Array(values.clone()));
+                    set_clauses.push(format!(
+                        "{0} = list_append(if_not_exists({0}, {1}), {2})",
+                        path, empty, values
+                    ));
+                }
+                UpdateAction::Remove(path) => remove_clauses.push(expression.name_path(path)),
+            }
+        }
+
+        let mut clauses = vec![];
+        if !set_clauses.is_empty() {
+            clauses.push(format!("SET {}", set_clauses.join(", ")));
+        }
+        if !remove_clauses.is_empty() {
+            clauses.push(format!("REMOVE {}", remove_clauses.join(", ")));
+        }
+        expression.update_expression = clauses.join(" ");
+
+        let conditions: Vec<String> = self
+            .conditions
+            .iter()
+            .map(|condition| match condition {
+                UpdateCondition::Exists(path) => {
+                    format!("attribute_exists({})", expression.name_path(path))
+                }
+                UpdateCondition::NotExists(path) => {
+                    format!("attribute_not_exists({})", expression.name_path(path))
+                }
+                UpdateCondition::Equals(path, value) => {
+                    let path = expression.name_path(path);
+                    format!("{} = {}", path, expression.value(value.clone()))
+                }
+            })
+            .collect();
+        if !conditions.is_empty() {
+            expression.condition_expression = Some(conditions.join(" AND "));
+        }
+
+        expression
+    }
+}
+
+impl UpdateExpression {
+    /// `#n0.#n1`, reusing the placeholder of names seen before.
+    fn name_path(&mut self, path: &[String]) -> String {
+        path.iter()
+            .map(|name| {
+                let existing = self
+                    .attribute_names
+                    .iter()
+                    .find(|(_, existing_name)| *existing_name == name)
+                    .map(|(placeholder, _)| placeholder.to_owned());
+                existing.unwrap_or_else(|| {
+                    let placeholder = format!("#n{}", self.attribute_names.len());
+                    self.attribute_names
+                        .insert(placeholder.clone(), name.to_owned());
+                    placeholder
+                })
+            })
+            .collect::<Vec<String>>()
+            .join(".")
+    }
+
+    fn value(&mut self, value: Value) -> String {
+        let placeholder = format!(":v{}", self.attribute_values.len());
+        self.attribute_values.insert(placeholder.clone(), value);
+        placeholder
+    }
+}
+
+/// DynamoDB's error message for an update of a key inside a map that doesn't exist.
+pub(crate) const INVALID_DOCUMENT_PATH: &str =
+    "The document path provided in the update expression is invalid for update";
+
+/// Whether DynamoDB rejected an update for a path inside a map that doesn't exist, rather than
+/// for another validation error like a reserved word or an item too large.
+fn is_invalid_document_path(code: Option<&str>, message: Option<&str>) -> bool {
+    code == Some("ValidationException")
+        && message.map_or(false, |message| message.contains(INVALID_DOCUMENT_PATH))
+}
+
+/// Apply `update` to the item whose primary key `key.0` has the value `key.1`.
+///
+/// Returns a [`SoclessError::ConditionFailed`] if a condition of `update` isn't met, and a
+/// [`SoclessError::InvalidUpdate`] if DynamoDB rejects a path of `update` as invalid for the item.
+/// Without a condition, updating an item that doesn't exist creates it.
+/// ## Example
+/// ```ignore
+/// update_item_in_table(
+///     &message_responses_table_name,
+///     ("message_id", &message_id),
+///     &ItemUpdate::new()
+///         .set(&["fulfilled"], true)
+///         .condition_exists(&["message_id"]),
+/// )
+/// .await?;
+/// ```
+#[instrument(level = "debug", skip_all, fields(table = table_name, key = primary_key_value))]
 pub async fn update_item_in_table(
     table_name: &str,
-    table_item: impl serde::ser::Serialize,
-) -> Result<PutItemOutput, aws_sdk_dynamodb::SdkError<PutItemError>> {
+    (primary_key_name, primary_key_value): (&str, &str),
+    update: &ItemUpdate,
+) -> SoclessResult<UpdateItemOutput> {
+    if update.is_empty() {
+        return Err(SoclessError::InvalidInput(
+            "an ItemUpdate needs at least one action".to_owned(),
+        ));
+    }
+    let expression = update.build();
+
+    let mut attribute_values = HashMap::new();
+    for (placeholder, value) in expression.attribute_values {
+        attribute_values.insert(placeholder, to_attribute_value(value)?);
+    }
+
     get_or_init_dynamo()
-        .await
-        .put_item()
+        .await?
+        .update_item()
         .table_name(table_name)
-        .set_item(Some(to_item(table_item).unwrap()))
+        .key(primary_key_name, to_attribute_value(primary_key_value)?)
+        .update_expression(expression.update_expression)
+        .set_condition_expression(expression.condition_expression)
+        .set_expression_attribute_names(Some(expression.attribute_names))
+        // DynamoDB rejects an empty map of values
+        .set_expression_attribute_values(Some(attribute_values).filter(|values| !values.is_empty()))
         .send()
         .await
+        .map_err(|e| match e {
+            SdkError::ServiceError { err, .. } if err.is_conditional_check_failed_exception() => {
+                SoclessError::ConditionFailed(format!(
+                    "update_item of table: {} for key= {{ {} : {} }}",
+                    table_name, primary_key_name, primary_key_value
+                ))
+            }
+            SdkError::ServiceError { err, .. }
+                if is_invalid_document_path(err.code(), err.message()) =>
+            {
+                SoclessError::InvalidUpdate(format!(
+                    "update_item of table: {} for key= {{ {} : {} }}: {}",
+                    table_name,
+                    primary_key_name,
+                    primary_key_value,
+                    err.message().unwrap_or_default()
+                ))
+            }
+            e => SoclessError::Storage(format!(
+                "Error in update_item of table: {} for key= {{ {} : {} }}: {}",
+                table_name, primary_key_name, primary_key_value, e
+            )),
+        })
 }
 
 use crate::clients::get_or_init_s3;
-use aws_sdk_s3::{error::GetObjectError, output::GetObjectOutput};
-use serde_json::Value;
-use std::env::var;
+use aws_sdk_s3::output::GetObjectOutput;
+
+pub use crate::vault::fetch_utf8_from_vault;
 
 /// Combine two serde Value objects
 /// # Example
@@ -114,34 +448,27 @@ pub fn json_merge(a: &mut Value, b: Value) {
     *a = b;
 }
 
-pub async fn get_object_from_s3(
-    key: &str,
-    bucket_name: &str,
-) -> Result<GetObjectOutput, aws_sdk_s3::SdkError<GetObjectError>> {
+pub async fn get_object_from_s3(key: &str, bucket_name: &str) -> SoclessResult<GetObjectOutput> {
     get_or_init_s3()
-        .await
+        .await?
         .get_object()
         .bucket(bucket_name)
         .key(key)
         .send()
         .await
-}
-
-pub async fn fetch_utf8_from_vault(key: &str) -> String {
-    let socless_vault_bucket_name: String =
-        var(&"SOCLESS_VAULT").expect("No env var found for SOCLESS_VAULT s3 bucket");
-
-    let object_result = get_object_from_s3(key, &socless_vault_bucket_name).await;
-    let object = object_result.unwrap_or_else(|_| panic!("No object found for key: {}", key));
-
-    let body_as_bytes = object.body.collect().await.unwrap().into_bytes();
-
-    String::from_utf8(body_as_bytes.to_vec()).expect("S3 file is not valid utf8")
+        .map_err(|e| {
+            SoclessError::Vault(format!(
+                "No object found for key: {} in bucket: {}: {}",
+                key, bucket_name, e
+            ))
+        })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use maplit::hashmap;
+    use serde_json::json;
 
     #[test]
     fn test_gen_id() {
@@ -152,4 +479,82 @@ mod tests {
     fn test_gen_datetimenow() {
         assert_eq!(27, gen_datetimenow().len());
     }
+
+    #[test]
+    fn test_item_update_expression() {
+        let expression = ItemUpdate::new()
+            .set_if_not_exists(&["results", "errors"], json!({}))
+            .set(
+                &["results", "results", "Fetch Logs.v2"],
+                json!({"count": 3}),
+            )
+            .append(&["history"], vec![json!("attempt 1")])
+            .remove(&["results", "stale"])
+            .condition_exists(&["execution_id"])
+            .condition_equals(&["status"], "open")
+            .build();
+
+        assert_eq!(
+            expression.update_expression,
+            "SET #n0.#n1 = if_not_exists(#n0.#n1, :v0), #n0.#n0.#n2 = :v1, \
+             #n3 = list_append(if_not_exists(#n3, :v2), :v3) REMOVE #n0.#n4"
+        );
+        assert_eq!(
+            expression.condition_expression.as_deref(),
+            Some("attribute_exists(#n5) AND #n6 = :v4")
+        );
+        assert_eq!(
+            expression.attribute_names,
+            hashmap! {
+                "#n0".to_string() => "results".to_string(),
+                "#n1".to_string() => "errors".to_string(),
+                "#n2".to_string() => "Fetch Logs.v2".to_string(),
+                "#n3".to_string() => "history".to_string(),
+                "#n4".to_string() => "stale".to_string(),
+                "#n5".to_string() => "execution_id".to_string(),
+                "#n6".to_string() => "status".to_string(),
+            }
+        );
+        assert_eq!(
+            expression.attribute_values,
+            hashmap! {
+                ":v0".to_string() => json!({}),
+                ":v1".to_string() => json!({"count": 3}),
+                ":v2".to_string() => json!([]),
+                ":v3".to_string() => json!(["attempt 1"]),
+                ":v4".to_string() => json!("open"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_item_update_without_set_or_conditions() {
+        let expression = ItemUpdate::new().remove(&["a"]).remove(&["b"]).build();
+        assert_eq!(expression.update_expression, "REMOVE #n0, #n1");
+        assert_eq!(expression.condition_expression, None);
+        assert!(expression.attribute_values.is_empty());
+        assert!(ItemUpdate::new().is_empty());
+    }
+
+    #[test]
+    fn test_is_invalid_document_path() {
+        let validation = Some("ValidationException");
+        assert!(is_invalid_document_path(
+            validation,
+            Some(INVALID_DOCUMENT_PATH)
+        ));
+        assert!(!is_invalid_document_path(
+            validation,
+            Some("Invalid UpdateExpression: Attribute name is a reserved keyword; reserved keyword: status")
+        ));
+        assert!(!is_invalid_document_path(
+            validation,
+            Some("Item size has exceeded the maximum allowed size")
+        ));
+        assert!(!is_invalid_document_path(validation, None));
+        assert!(!is_invalid_document_path(
+            Some("ConditionalCheckFailedException"),
+            Some(INVALID_DOCUMENT_PATH)
+        ));
+    }
 }
@@ -0,0 +1,724 @@
+//! The SOCless vault, storage for payloads too large to pass between States.
+//!
+//! Files are referenced in State parameters as `vault:<file_id>`. The vault is an S3 bucket by
+//! default, set `SOCLESS_VAULT_BACKEND=local` (or `vault_backend` in the [`SoclessConfig`]) to use
+//! a local directory instead (tests, offline development). Either way `SOCLESS_VAULT` holds the
+//! bucket name or directory path.
+//!
+//! Set `SOCLESS_VAULT_KMS_KEY_ID` to encrypt files client-side with that KMS key, see
+//! [`crate::encryption`].
+//!
+//! [`SoclessConfig`]: crate::config::SoclessConfig
+
+use crate::{
+    clients::get_or_init_s3,
+    config::{get_or_init_config, SoclessConfig, VaultBackendKind},
+    encryption::{EncryptedVault, KmsKeyProvider},
+    errors::{SoclessError, SoclessResult},
+    utils::{gen_id, get_object_from_s3},
+};
+use async_trait::async_trait;
+use aws_sdk_s3::ByteStream;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+use tokio::{io::AsyncRead, sync::OnceCell};
+use tokio_util::io::StreamReader;
+use tracing::instrument;
+
+pub const VAULT_TOKEN: &str = "vault:";
+
+/// Content type of State results saved by [`offload_oversized_result`], which the parameter
+/// resolver parses back into json.
+pub const OFFLOADED_RESULT_CONTENT_TYPE: &str = "application/vnd.socless.state-result+json";
+
+/// State results that serialize to more bytes than this are offloaded to the vault, keeping
+/// results table items under DynamoDB's 400KB limit and Lambda outputs under Step Functions' 256KB.
+pub const DEFAULT_OFFLOAD_THRESHOLD_BYTES: usize = 100 * 1024;
+
+/// DynamoDB's limit on the size of an item, e.g. an execution's results item.
+pub const DYNAMO_ITEM_LIMIT_BYTES: usize = 400 * 1024;
+
+/// Step Functions' limit on the size of a State's input and output.
+pub const STEP_FUNCTIONS_PAYLOAD_LIMIT_BYTES: usize = 256 * 1024;
+
+/// Key of the object returned by the Lambda in place of a result too large for Step Functions,
+/// `{"vault_ref": "vault:<file_id>"}`.
+pub const VAULT_REF_KEY: &str = "vault_ref";
+
+/// A result is saved twice in the results item, under the State's name and `_Last_Saved_Results`.
+const SAVED_RESULT_COPIES: usize = 2;
+
+/// Room left in the results item for the State's metadata, attempt and attribute names.
+const RESULTS_ITEM_HEADROOM_BYTES: usize = 16 * 1024;
+
+/// A file saved to the vault.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct VaultObject {
+    pub file_id: String,
+    /// `vault:<file_id>`, ready to be passed to another State.
+    pub vault_id: String,
+}
+
+impl VaultObject {
+    pub fn new(file_id: &str) -> Self {
+        VaultObject {
+            file_id: file_id.to_owned(),
+            vault_id: format!("{}{}", VAULT_TOKEN, file_id),
+        }
+    }
+}
+
+/// A vault file's content and the content type it was saved with.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VaultContent {
+    pub content: Vec<u8>,
+    pub content_type: Option<String>,
+}
+
+/// A file listed by [`list_vault`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct VaultFile {
+    pub file_id: String,
+    pub vault_id: String,
+    pub size: i64,
+}
+
+impl VaultFile {
+    pub fn new(file_id: String, size: i64) -> Self {
+        VaultFile {
+            vault_id: VaultObject::new(&file_id).vault_id,
+            file_id,
+            size,
+        }
+    }
+}
+
+/// Storage backend for vault files.
+///
+/// Every vault read and write made by this crate goes through the backend returned by
+/// [`get_or_init_vault`], which is chosen by the `SOCLESS_VAULT_BACKEND` environment variable.
+/// Call [`set_vault_backend`] before any other socless function to use a different backend.
+#[async_trait]
+pub trait VaultBackend: Send + Sync {
+    async fn put(
+        &self,
+        file_id: &str,
+        content: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> SoclessResult<()>;
+
+    async fn get(&self, file_id: &str) -> SoclessResult<VaultContent>;
+
+    async fn get_stream(&self, file_id: &str) -> SoclessResult<ByteStream>;
+
+    /// Files whose id starts with `prefix` (every file if `None`), ordered by id.
+    async fn list(&self, prefix: Option<&str>) -> SoclessResult<Vec<VaultFile>>;
+
+    /// Deleting a file that doesn't exist is not an error.
+    async fn delete(&self, file_id: &str) -> SoclessResult<()>;
+}
+
+pub static VAULT_BACKEND: OnceCell<Box<dyn VaultBackend>> = OnceCell::const_new();
+pub async fn get_or_init_vault() -> SoclessResult<&'static dyn VaultBackend> {
+    let backend = VAULT_BACKEND
+        .get_or_try_init(|| async { vault_backend_from_config(get_or_init_config().await?) })
+        .await?;
+    Ok(backend.as_ref())
+}
+
+/// Replace the vault backend chosen by the [`SoclessConfig`] with a custom [`VaultBackend`].
+///
+/// Returns `false` if a backend was already initialized, in which case `backend` is dropped.
+pub fn set_vault_backend(backend: impl VaultBackend + 'static) -> bool {
+    VAULT_BACKEND.set(Box::new(backend)).is_ok()
+}
+
+fn vault_backend_from_config(config: &SoclessConfig) -> SoclessResult<Box<dyn VaultBackend>> {
+    let backend: Box<dyn VaultBackend> = match config.vault_backend {
+        VaultBackendKind::S3 => Box::new(S3Vault::default()),
+        VaultBackendKind::Local => Box::new(LocalVault::new(config.vault()?)),
+    };
+
+    match &config.vault_kms_key_id {
+        Some(key_id) => Ok(Box::new(
+            EncryptedVault::from_boxed(backend, Box::new(KmsKeyProvider::new(key_id)))
+                .allow_plaintext(config.vault_allow_plaintext),
+        )),
+        None => Ok(backend),
+    }
+}
+
+async fn vault_bucket() -> SoclessResult<String> {
+    Ok(get_or_init_config().await?.vault()?.to_owned())
+}
+
+/// The default [`VaultBackend`], the S3 bucket named in `SOCLESS_VAULT`.
+#[derive(Debug, Default, Clone)]
+pub struct S3Vault {}
+
+#[async_trait]
+impl VaultBackend for S3Vault {
+    async fn put(
+        &self,
+        file_id: &str,
+        content: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> SoclessResult<()> {
+        let bucket = vault_bucket().await?;
+
+        get_or_init_s3()
+            .await?
+            .put_object()
+            .bucket(&bucket)
+            .key(file_id)
+            .body(ByteStream::from(content))
+            .set_content_type(content_type.map(str::to_owned))
+            .send()
+            .await
+            .map_err(|e| {
+                SoclessError::Vault(format!(
+                    "Unable to save vault file {} to bucket: {}: {}",
+                    file_id, bucket, e
+                ))
+            })?;
+        Ok(())
+    }
+
+    async fn get(&self, file_id: &str) -> SoclessResult<VaultContent> {
+        let object = get_object_from_s3(file_id, &vault_bucket().await?).await?;
+        let content_type = object.content_type;
+
+        let content = object
+            .body
+            .collect()
+            .await
+            .map_err(|e| {
+                SoclessError::Vault(format!("Unable to read vault file {}: {}", file_id, e))
+            })?
+            .into_bytes()
+            .to_vec();
+
+        Ok(VaultContent {
+            content,
+            content_type,
+        })
+    }
+
+    async fn get_stream(&self, file_id: &str) -> SoclessResult<ByteStream> {
+        Ok(get_object_from_s3(file_id, &vault_bucket().await?)
+            .await?
+            .body)
+    }
+
+    async fn list(&self, prefix: Option<&str>) -> SoclessResult<Vec<VaultFile>> {
+        let bucket = vault_bucket().await?;
+        let client = get_or_init_s3().await?;
+
+        let mut files = vec![];
+        let mut continuation_token = None;
+        loop {
+            let page = client
+                .list_objects_v2()
+                .bucket(&bucket)
+                .set_prefix(prefix.map(str::to_owned))
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(|e| {
+                    SoclessError::Vault(format!("Unable to list vault bucket: {}: {}", bucket, e))
+                })?;
+
+            for object in page.contents.unwrap_or_default() {
+                if let Some(file_id) = object.key {
+                    files.push(VaultFile::new(file_id, object.size));
+                }
+            }
+
+            match page.next_continuation_token {
+                Some(token) if page.is_truncated => continuation_token = Some(token),
+                _ => return Ok(files),
+            }
+        }
+    }
+
+    async fn delete(&self, file_id: &str) -> SoclessResult<()> {
+        let bucket = vault_bucket().await?;
+
+        get_or_init_s3()
+            .await?
+            .delete_object()
+            .bucket(&bucket)
+            .key(file_id)
+            .send()
+            .await
+            .map_err(|e| {
+                SoclessError::Vault(format!(
+                    "Unable to delete vault file {} from bucket: {}: {}",
+                    file_id, bucket, e
+                ))
+            })?;
+        Ok(())
+    }
+}
+
+/// A [`VaultBackend`] storing each file under a local directory, with content types kept in a
+/// `.socless-meta` subdirectory.
+///
+/// Meant for tests and offline development, it uses blocking file IO and reads whole files.
+#[derive(Debug, Clone)]
+pub struct LocalVault {
+    root: PathBuf,
+}
+
+const LOCAL_META_DIR: &str = ".socless-meta";
+
+impl LocalVault {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalVault { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of a file, rejecting ids that would escape the vault directory.
+    fn file_path(&self, file_id: &str) -> SoclessResult<PathBuf> {
+        let relative = Path::new(file_id);
+        let is_contained = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if file_id.is_empty() || !is_contained || file_id.starts_with(LOCAL_META_DIR) {
+            return Err(SoclessError::Vault(format!(
+                "Invalid vault file id: {}",
+                file_id
+            )));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    fn content_type_path(&self, file_id: &str) -> PathBuf {
+        self.root
+            .join(LOCAL_META_DIR)
+            .join(format!("{}.content-type", file_id))
+    }
+
+    fn io_error(&self, action: &str, file_id: &str, e: io::Error) -> SoclessError {
+        SoclessError::Vault(format!(
+            "Unable to {} vault file {} in directory: {}: {}",
+            action,
+            file_id,
+            self.root.display(),
+            e
+        ))
+    }
+
+    fn collect_files(&self, dir: &Path, files: &mut Vec<VaultFile>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path == self.root.join(LOCAL_META_DIR) {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                self.collect_files(&path, files)?;
+            } else if let Ok(relative) = path.strip_prefix(&self.root) {
+                let file_id = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.push(VaultFile::new(file_id, entry.metadata()?.len() as i64));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_creating_dirs(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+#[async_trait]
+impl VaultBackend for LocalVault {
+    async fn put(
+        &self,
+        file_id: &str,
+        content: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> SoclessResult<()> {
+        write_creating_dirs(&self.file_path(file_id)?, &content)
+            .map_err(|e| self.io_error("save", file_id, e))?;
+
+        let content_type_path = self.content_type_path(file_id);
+        match content_type {
+            Some(content_type) => write_creating_dirs(&content_type_path, content_type.as_bytes()),
+            None => remove_if_exists(&content_type_path),
+        }
+        .map_err(|e| self.io_error("save", file_id, e))
+    }
+
+    async fn get(&self, file_id: &str) -> SoclessResult<VaultContent> {
+        let content =
+            fs::read(self.file_path(file_id)?).map_err(|e| self.io_error("read", file_id, e))?;
+        let content_type = fs::read_to_string(self.content_type_path(file_id)).ok();
+
+        Ok(VaultContent {
+            content,
+            content_type,
+        })
+    }
+
+    async fn get_stream(&self, file_id: &str) -> SoclessResult<ByteStream> {
+        Ok(ByteStream::from(self.get(file_id).await?.content))
+    }
+
+    async fn list(&self, prefix: Option<&str>) -> SoclessResult<Vec<VaultFile>> {
+        let mut files = vec![];
+        if self.root.exists() {
+            self.collect_files(&self.root, &mut files)
+                .map_err(|e| self.io_error("list", "*", e))?;
+        }
+
+        files.retain(|file| file.file_id.starts_with(prefix.unwrap_or_default()));
+        files.sort_by(|a, b| a.file_id.cmp(&b.file_id));
+        Ok(files)
+    }
+
+    async fn delete(&self, file_id: &str) -> SoclessResult<()> {
+        remove_if_exists(&self.file_path(file_id)?)
+            .and_then(|_| remove_if_exists(&self.content_type_path(file_id)))
+            .map_err(|e| self.io_error("delete", file_id, e))
+    }
+}
+
+/// Save content to the vault under a generated id.
+/// # Example
+/// ```ignore
+/// use socless::vault::save_to_vault;
+///
+/// let report = save_to_vault("<html>...</html>", Some("text/html")).await?;
+/// Ok(json!({ "report": report.vault_id }))
+/// ```
+#[instrument(level = "debug", skip_all, fields(content_type = ?content_type))]
+pub async fn save_to_vault(
+    content: impl Into<Vec<u8>>,
+    content_type: Option<&str>,
+) -> SoclessResult<VaultObject> {
+    let file_id = gen_id();
+    get_or_init_vault()
+        .await?
+        .put(&file_id, content.into(), content_type)
+        .await?;
+    Ok(VaultObject::new(&file_id))
+}
+
+/// Byte size above which State results are offloaded, `result_offload_bytes` in the
+/// [`SoclessConfig`] (`SOCLESS_RESULT_OFFLOAD_BYTES`).
+pub async fn offload_threshold() -> SoclessResult<usize> {
+    Ok(get_or_init_config().await?.result_offload_bytes)
+}
+
+/// A State result as saved to the results table and as returned by the Lambda, see
+/// [`offload_oversized_result`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateOutput {
+    /// The result, or its `vault:` reference if it was offloaded.
+    pub saved: Value,
+    /// The result, or `{"vault_ref": "vault:<file_id>"}` if it is too large for Step Functions.
+    pub output: Value,
+}
+
+impl StateOutput {
+    /// A result that is saved and returned as is.
+    pub fn unchanged(result: Value) -> Self {
+        StateOutput {
+            saved: result.clone(),
+            output: result,
+        }
+    }
+}
+
+/// Save a State result to the vault if its `saved` form (the result as saved to the results
+/// table, e.g. redacted) is over the [`offload_threshold`] or would push the results item,
+/// currently `item_bytes` large, over DynamoDB's item limit, or if the `result` returned by the
+/// Lambda is over Step Functions' payload limit.
+///
+/// An offloaded result is saved to the results table as its `vault:` reference, which the
+/// parameter resolver rehydrates. The Lambda still returns the result itself so later States and
+/// `ResultPath`s get the object they expect, unless it is over Step Functions' payload limit and
+/// `{"vault_ref": "vault:<file_id>"}` is returned instead. Each limit is checked against the
+/// value it applies to, so redacted fields never decide whether the saved copy is offloaded.
+pub async fn offload_oversized_result(
+    result: Value,
+    saved: Value,
+    item_bytes: usize,
+) -> SoclessResult<StateOutput> {
+    let serialized = serde_json::to_vec(&saved)?;
+    let saved_bytes = serialized.len();
+    let item_bytes_after_save =
+        item_bytes + SAVED_RESULT_COPIES * saved_bytes + RESULTS_ITEM_HEADROOM_BYTES;
+    let fits_results_item = saved_bytes <= offload_threshold().await?
+        && item_bytes_after_save <= DYNAMO_ITEM_LIMIT_BYTES;
+    let fits_step_functions =
+        serde_json::to_vec(&result)?.len() <= STEP_FUNCTIONS_PAYLOAD_LIMIT_BYTES;
+    if fits_results_item && fits_step_functions {
+        return Ok(StateOutput {
+            saved,
+            output: result,
+        });
+    }
+
+    let vault_id = save_to_vault(serialized, Some(OFFLOADED_RESULT_CONTENT_TYPE))
+        .await?
+        .vault_id;
+    Ok(StateOutput {
+        saved: match fits_results_item {
+            true => saved,
+            false => Value::String(vault_id.clone()),
+        },
+        output: match fits_step_functions {
+            true => result,
+            false => json!({ VAULT_REF_KEY: vault_id }),
+        },
+    })
+}
+
+/// Fetch a vault file and its content type.
+#[instrument(level = "debug")]
+pub async fn fetch_from_vault(file_id: &str) -> SoclessResult<VaultContent> {
+    get_or_init_vault().await?.get(file_id).await
+}
+
+/// Fetch the raw bytes of a vault file.
+pub async fn fetch_bytes_from_vault(file_id: &str) -> SoclessResult<Vec<u8>> {
+    Ok(fetch_from_vault(file_id).await?.content)
+}
+
+/// Stream a vault file's body, for files too large to hold in memory.
+#[instrument(level = "debug")]
+pub async fn fetch_stream_from_vault(file_id: &str) -> SoclessResult<ByteStream> {
+    get_or_init_vault().await?.get_stream(file_id).await
+}
+
+/// Read a vault file as a [`tokio::io::AsyncRead`].
+/// # Example
+/// ```ignore
+/// use socless::vault::vault_reader;
+///
+/// let mut pcap = vault_reader("capture.pcap").await?;
+/// let mut file = tokio::fs::File::create("/tmp/capture.pcap").await?;
+/// tokio::io::copy(&mut pcap, &mut file).await?;
+/// ```
+pub async fn vault_reader(file_id: &str) -> SoclessResult<impl AsyncRead + Send + Unpin> {
+    let body = fetch_stream_from_vault(file_id)
+        .await?
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+    Ok(StreamReader::new(Box::pin(body)))
+}
+
+/// Fetch a vault file that contains utf8 text.
+pub async fn fetch_utf8_from_vault(file_id: &str) -> SoclessResult<String> {
+    String::from_utf8(fetch_bytes_from_vault(file_id).await?)
+        .map_err(|_| SoclessError::Vault(format!("Vault file {} is not valid utf8", file_id)))
+}
+
+/// List the files in the vault, optionally only those whose id starts with `prefix`.
+pub async fn list_vault(prefix: Option<&str>) -> SoclessResult<Vec<VaultFile>> {
+    get_or_init_vault().await?.list(prefix).await
+}
+
+/// Delete a file from the vault. Deleting a file that doesn't exist is not an error.
+#[instrument(level = "debug")]
+pub async fn delete_from_vault(file_id: &str) -> SoclessResult<()> {
+    get_or_init_vault().await?.delete(file_id).await
+}
+
+/// A vault directory with the files used by unit tests, shared by every test in the crate.
+#[cfg(test)]
+pub(crate) async fn use_test_vault() -> &'static Path {
+    static TEST_VAULT_DIR: OnceCell<PathBuf> = OnceCell::const_new();
+    TEST_VAULT_DIR
+        .get_or_init(|| async {
+            let root = std::env::temp_dir().join(format!("socless-vault-{}", gen_id()));
+            fs::create_dir_all(&root).unwrap();
+            fs::write(
+                root.join("socless_vault_tests.txt"),
+                "this came from the vault",
+            )
+            .unwrap();
+            fs::write(
+                root.join("socless_vault_tests.json"),
+                r#"{"hello": "world"}"#,
+            )
+            .unwrap();
+            assert!(
+                set_vault_backend(LocalVault::new(&root)),
+                "the vault backend was initialized before use_test_vault"
+            );
+            root
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_object_reference() {
+        let vault_object = VaultObject::new("1234-abcd");
+        assert_eq!(vault_object.file_id, "1234-abcd");
+        assert_eq!(vault_object.vault_id, "vault:1234-abcd");
+    }
+
+    #[tokio::test]
+    async fn test_s3_vault_without_bucket() {
+        let result = S3Vault::default().put("file_id", vec![], None).await;
+        assert!(matches!(result, Err(SoclessError::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn test_local_vault_save_fetch_list_delete() {
+        use_test_vault().await;
+
+        let saved = save_to_vault("evidence", Some("text/plain")).await.unwrap();
+        let fetched = fetch_from_vault(&saved.file_id).await.unwrap();
+        assert_eq!(fetched.content, b"evidence");
+        assert_eq!(fetched.content_type.as_deref(), Some("text/plain"));
+
+        let listed = list_vault(Some(&saved.file_id)).await.unwrap();
+        assert_eq!(listed, vec![VaultFile::new(saved.file_id.clone(), 8)]);
+
+        delete_from_vault(&saved.file_id).await.unwrap();
+        assert!(matches!(
+            fetch_from_vault(&saved.file_id).await,
+            Err(SoclessError::Vault(_))
+        ));
+        delete_from_vault(&saved.file_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_vault_nested_ids_and_streams() {
+        use tokio::io::AsyncReadExt;
+
+        let vault = LocalVault::new(use_test_vault().await.join("nested-test"));
+        vault
+            .put("reports/2022/report.html", b"<html/>".to_vec(), None)
+            .await
+            .unwrap();
+
+        let listed = vault.list(Some("reports/")).await.unwrap();
+        assert_eq!(
+            listed,
+            vec![VaultFile::new("reports/2022/report.html".to_owned(), 7)]
+        );
+
+        let mut reader = StreamReader::new(Box::pin(
+            vault
+                .get_stream("reports/2022/report.html")
+                .await
+                .unwrap()
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e)),
+        ));
+        let mut content = String::new();
+        reader.read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "<html/>");
+    }
+
+    #[tokio::test]
+    async fn test_local_vault_rejects_escaping_ids() {
+        let vault = LocalVault::new(use_test_vault().await);
+        for file_id in [
+            "../outside",
+            "/etc/passwd",
+            "a/../../b",
+            ".socless-meta/x",
+            "",
+        ] {
+            assert!(
+                matches!(vault.get(file_id).await, Err(SoclessError::Vault(_))),
+                "{} should be rejected",
+                file_id
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offload_oversized_result() {
+        use_test_vault().await;
+
+        let small_result = json!({"status": "ok"});
+        assert_eq!(
+            offload_oversized_result(small_result.clone(), small_result.clone(), 0)
+                .await
+                .unwrap(),
+            StateOutput::unchanged(small_result)
+        );
+
+        let large_result = json!({ "log": "a".repeat(DEFAULT_OFFLOAD_THRESHOLD_BYTES) });
+        let offloaded = offload_oversized_result(large_result.clone(), large_result.clone(), 0)
+            .await
+            .unwrap();
+        assert_eq!(offloaded.output, large_result);
+        let file_id = offloaded
+            .saved
+            .as_str()
+            .and_then(|vault_id| vault_id.strip_prefix(VAULT_TOKEN))
+            .unwrap();
+
+        let content = fetch_from_vault(file_id).await.unwrap();
+        assert_eq!(
+            content.content_type.as_deref(),
+            Some(OFFLOADED_RESULT_CONTENT_TYPE)
+        );
+        assert_eq!(
+            serde_json::from_slice::<Value>(&content.content).unwrap(),
+            large_result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_offload_result_that_would_fill_the_results_item() {
+        use_test_vault().await;
+
+        let result = json!({ "log": "a".repeat(DEFAULT_OFFLOAD_THRESHOLD_BYTES / 2) });
+        assert_eq!(
+            offload_oversized_result(result.clone(), result.clone(), 0)
+                .await
+                .unwrap(),
+            StateOutput::unchanged(result.clone())
+        );
+
+        let offloaded = offload_oversized_result(result.clone(), result.clone(), 300 * 1024)
+            .await
+            .unwrap();
+        assert!(offloaded.saved.as_str().unwrap().starts_with(VAULT_TOKEN));
+        assert_eq!(offloaded.output, result);
+    }
+
+    #[tokio::test]
+    async fn test_offload_result_too_large_for_step_functions() {
+        use_test_vault().await;
+
+        let result = json!({ "log": "a".repeat(STEP_FUNCTIONS_PAYLOAD_LIMIT_BYTES) });
+        let offloaded = offload_oversized_result(result.clone(), result, 0)
+            .await
+            .unwrap();
+        assert_eq!(offloaded.output, json!({ VAULT_REF_KEY: offloaded.saved }));
+    }
+}
@@ -0,0 +1,159 @@
+//! The [`DynamoStore`] against DynamoDB in a Localstack container.
+//!
+//! Needs Docker, run with `cargo test --test dynamo_store -- --ignored`.
+#[allow(dead_code)]
+mod localstack_setup;
+
+use aws_sdk_dynamodb::model::{
+    AttributeDefinition, BillingMode, KeySchemaElement, KeyType, ScalarAttributeType,
+};
+use localstack_setup::{wait_for_localstack_container, LocalstackDynamo};
+use serde_json::{json, Value};
+use socless::{
+    get_item, set_clients, set_config, utils::put_item_in_table, DynamoStore, ReadConsistency,
+    ResultsTableItem, SoclessClients, SoclessConfig, SoclessError, SoclessStore,
+};
+use std::collections::HashMap;
+use testcontainers::clients::Cli;
+
+const RESULTS_TABLE: &str = "socless_execution_results";
+
+async fn create_results_table(clients: &SoclessClients) {
+    clients
+        .dynamo
+        .create_table()
+        .table_name(RESULTS_TABLE)
+        .key_schema(
+            KeySchemaElement::builder()
+                .attribute_name("execution_id")
+                .key_type(KeyType::Hash)
+                .build(),
+        )
+        .attribute_definitions(
+            AttributeDefinition::builder()
+                .attribute_name("execution_id")
+                .attribute_type(ScalarAttributeType::S)
+                .build(),
+        )
+        .billing_mode(BillingMode::PayPerRequest)
+        .send()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs docker"]
+async fn test_dynamo_store_saves_state_results() {
+    let docker = Cli::default();
+    let container = docker.run(LocalstackDynamo::default());
+    let endpoint_url = format!("http://localhost:{}/", container.get_host_port(4566));
+    wait_for_localstack_container(endpoint_url.clone())
+        .await
+        .unwrap();
+
+    std::env::set_var("AWS_REGION", "us-east-1");
+    std::env::set_var("AWS_ACCESS_KEY_ID", "test");
+    std::env::set_var("AWS_SECRET_ACCESS_KEY", "test");
+    let aws_config = aws_config::load_from_env().await;
+    let clients = SoclessClients::with_endpoint_url(&aws_config, &endpoint_url).unwrap();
+    create_results_table(&clients).await;
+    set_clients(clients);
+    set_config(
+        SoclessConfig::builder()
+            .results_table(RESULTS_TABLE)
+            .build()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let store = DynamoStore::default();
+    store
+        .put_execution_results(&ResultsTableItem {
+            execution_id: "exec-1".to_owned(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // other States' errors are kept
+    store
+        .save_state_error("exec-1", "Get_Manager", &json!({"error": "not found"}))
+        .await
+        .unwrap();
+    let errors: HashMap<_, _> = [("Get_User".to_owned(), json!({"error": "timed out"}))]
+        .into_iter()
+        .collect();
+    store
+        .update_state_results(
+            "exec-1",
+            "Get_User",
+            &json!({"user": "sterling"}),
+            Some(&errors),
+            None,
+        )
+        .await
+        .unwrap();
+
+    let item = store
+        .get_execution_results("exec-1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        item.results.results["Get_User"],
+        json!({"user": "sterling"})
+    );
+    assert_eq!(
+        item.results.results["_Last_Saved_Results"],
+        json!({"user": "sterling"})
+    );
+    assert_eq!(
+        item.results.errors["Get_User"],
+        json!({"error": "timed out"})
+    );
+    assert_eq!(
+        item.results.errors["Get_Manager"],
+        json!({"error": "not found"})
+    );
+
+    // items written without the `results` maps get them on the first save
+    put_item_in_table(RESULTS_TABLE, json!({"execution_id": "exec-2"}))
+        .await
+        .unwrap();
+    store
+        .update_state_results(
+            "exec-2",
+            "Get_User",
+            &json!({"user": "sterling"}),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    store
+        .save_state_error("exec-2", "Get_Manager", &json!({"error": "not found"}))
+        .await
+        .unwrap();
+
+    let item: Value = get_item(
+        RESULTS_TABLE,
+        ("execution_id", "exec-2"),
+        ReadConsistency::Strong,
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        item["results"]["results"]["Get_User"],
+        json!({"user": "sterling"})
+    );
+    assert_eq!(
+        item["results"]["errors"]["Get_Manager"],
+        json!({"error": "not found"})
+    );
+
+    let missing = store
+        .update_state_results("exec-3", "Get_User", &json!({}), None, None)
+        .await;
+    assert!(matches!(missing, Err(SoclessError::NotFound { .. })));
+}
@@ -44,19 +44,19 @@ pub fn test_context_params_with_all_resolution_types() -> (StateConfig, SoclessC
             "Name" : "testing_all",
             "Parameters" : {
                 "test_age-jsonpath" : "$.artifacts.event.details.age",
-                // "test_age-jinja" : "{{context.artifacts.event.details.age}}",
+                "test_age-jinja" : "{{context.artifacts.event.details.age}}",
 
-                // "test_item_0-jsonpath" : "$.artifacts.event.details.items[0]",
-                // "test_item_0-jinja" : "{{context.artifacts.event.details.items[0]}}",
-                // "test_item_1_pin-jsonpath": "$.artifacts.event.details.items[1].pin",
-                // "test_item_1_pin-jinja": "{{context.artifacts.event.details.items[1].pin}}",
+                "test_item_0-jsonpath" : "$.artifacts.event.details.items[0]",
+                "test_item_0-jinja" : "{{context.artifacts.event.details.items[0]}}",
+                "test_item_1_pin-jsonpath": "$.artifacts.event.details.items[1].pin",
+                "test_item_1_pin-jinja": "{{context.artifacts.event.details.items[1].pin}}",
 
                 "test_weight-jsonpath" : "$.artifacts.event.details.weight",
-                // "test_weight-jinja" : "{{context.artifacts.event.details.weight}}",
+                "test_weight-jinja" : "{{context.artifacts.event.details.weight}}",
 
                 "test_secrets-jinja" : "asdf", // TODO
 
-                // "test_vault-jsonpath": "vault:socless_vault_tests.txt",
+                "test_vault-jsonpath": "vault:socless_vault_tests.txt",
                 // "test_vault-jinja": "vault:socless_vault_tests.txt",
             }
         }))
@@ -83,11 +83,21 @@ mod tests {
     use serde_json::{json, to_value};
 
     use crate::test_context_params_with_all_resolution_types;
+    use socless::{gen_id, set_vault_backend, LocalVault};
 
     #[tokio::test]
     async fn test_build_socless_boilerplate_with_complete_event_already_set_up() {
+        let vault_dir = std::env::temp_dir().join(format!("socless-vault-{}", gen_id()));
+        std::fs::create_dir_all(&vault_dir).unwrap();
+        std::fs::write(
+            vault_dir.join("socless_vault_tests.txt"),
+            "this came from the vault",
+        )
+        .unwrap();
+        set_vault_backend(LocalVault::new(vault_dir));
+
         let (mut state_config, context) = test_context_params_with_all_resolution_types();
-        state_config.resolve_parameters(&context).await;
+        state_config.resolve_parameters(&context).await.unwrap();
 
         let details = &context.artifacts.clone().unwrap()["event"]["details"];
 
@@ -95,19 +105,19 @@ mod tests {
             to_value(state_config.parameters).unwrap(),
             json!({
                 "test_age-jsonpath" : details["age"],
-                // "test_age-jinja" : details["age"],
+                "test_age-jinja" : details["age"],
 
-                // "test_item_0-jsonpath" : details["items"][0],
-                // "test_item_0-jinja" :details["items"][0],
-                // "test_item_1_pin-jsonpath":details["items"][1]["pin"],
-                // "test_item_1_pin-jinja":details["items"][1]["pin"],
+                "test_item_0-jsonpath" : details["items"][0],
+                "test_item_0-jinja" :details["items"][0],
+                "test_item_1_pin-jsonpath":details["items"][1]["pin"],
+                "test_item_1_pin-jinja":details["items"][1]["pin"],
 
                 "test_weight-jsonpath" :details["weight"],
-                // "test_weight-jinja" :details["weight"],
+                "test_weight-jinja" :details["weight"],
 
                 "test_secrets-jinja" : "asdf", // TODO
 
-                // "test_vault-jsonpath": "vault:socless_vault_tests.txt",
+                "test_vault-jsonpath": "this came from the vault",
                 // "test_vault-jinja": "vault:socless_vault_tests.txt",
             })
         )
@@ -0,0 +1,508 @@
+//! End to end tests of the SOCless flows against the in-memory store, no Localstack required.
+
+use lambda_runtime::Context;
+use serde::Deserialize;
+use serde_json::{from_value, json, Value};
+use socless::{
+    cancellation_token, create_events, end_human_interaction, init_human_interaction,
+    resolver::resolve_json_path,
+    set_config, set_store, set_vault_backend, socless_bootstrap,
+    vault::{DEFAULT_OFFLOAD_THRESHOLD_BYTES, DYNAMO_ITEM_LIMIT_BYTES},
+    EventTableItem, LocalVault, MemoryStore, PlaybookArtifacts, PlaybookInput, ResultDigest,
+    ResultsTableItem, SoclessConfig, SoclessContext, SoclessError, SoclessEventBatch, SoclessStore,
+    StateFailure, DEADLINE_MARGIN,
+};
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::OnceCell;
+
+static MEMORY_STORE: OnceCell<MemoryStore> = OnceCell::const_new();
+
+/// Every test in this binary shares the global store, so use unique ids per test.
+///
+/// Also sets the global config, pointing AWS clients at a closed port, and a local vault, so call
+/// this before anything that reads the config or the vault.
+async fn memory_store() -> &'static MemoryStore {
+    MEMORY_STORE
+        .get_or_init(|| async {
+            let store = MemoryStore::new();
+            set_store(store.clone());
+            let vault_dir =
+                std::env::temp_dir().join(format!("socless-vault-{}", socless::gen_id()));
+            assert!(set_vault_backend(LocalVault::new(vault_dir)));
+            set_config(
+                SoclessConfig::builder()
+                    .endpoint_url("http://127.0.0.1:9")
+                    .record_attempts(true)
+                    .redact_saved_results(true)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+            store
+        })
+        .await
+}
+
+async fn seed_execution(store: &MemoryStore, execution_id: &str) {
+    let event = EventTableItem {
+        id: format!("{}-investigation", execution_id),
+        investigation_id: format!("{}-investigation", execution_id),
+        status_: "open".to_string(),
+        event_type: "mock_test_event".to_string(),
+        playbook: "MockTestPlaybook".to_string(),
+        details: from_value(json!({"firstname": "Sterling", "lastname": "Archer"})).unwrap(),
+        ..Default::default()
+    };
+
+    store
+        .put_execution_results(&ResultsTableItem {
+            execution_id: execution_id.to_string(),
+            investigation_id: event.investigation_id.clone(),
+            datetime: socless::gen_datetimenow(),
+            results: PlaybookInput {
+                artifacts: PlaybookArtifacts {
+                    event,
+                    execution_id: execution_id.to_string(),
+                },
+                ..Default::default()
+            },
+        })
+        .await
+        .unwrap();
+}
+
+#[derive(Deserialize)]
+struct GreetParams {
+    firstname: String,
+}
+
+async fn greet(params: GreetParams) -> Result<Value, String> {
+    Ok(json!({ "greeting": format!("hello {}", params.firstname) }))
+}
+
+#[tokio::test]
+async fn test_socless_bootstrap_saves_state_results() {
+    let store = memory_store().await;
+    seed_execution(store, "bootstrap-exec").await;
+
+    let event = json!({
+        "execution_id": "bootstrap-exec",
+        "artifacts": store
+            .get_execution_results("bootstrap-exec")
+            .await
+            .unwrap()
+            .unwrap()
+            .results
+            .artifacts,
+        "State_Config": {
+            "Name": "Greet_User",
+            "Parameters": {
+                "firstname": "$.artifacts.event.details.firstname"
+            }
+        }
+    });
+
+    let output = socless_bootstrap(event, Context::default(), greet, false)
+        .await
+        .unwrap();
+    assert_eq!(output, json!({"greeting": "hello Sterling"}));
+
+    let saved = store
+        .get_execution_results("bootstrap-exec")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(saved.results.results["Greet_User"], output);
+    assert_eq!(saved.results.results["_Last_Saved_Results"], output);
+}
+
+async fn always_fails(_params: Value) -> Result<Value, String> {
+    Err("upstream API returned 503".to_string())
+}
+
+#[tokio::test]
+async fn test_socless_bootstrap_saves_state_failure_to_errors_map() {
+    let store = memory_store().await;
+    seed_execution(store, "bootstrap-failure-exec").await;
+
+    let event = json!({
+        "execution_id": "bootstrap-failure-exec",
+        "State_Config": { "Name": "Flaky_Lookup", "Parameters": {} }
+    });
+
+    let result = socless_bootstrap(event, Context::default(), always_fails, false).await;
+    let failure = match result {
+        Err(SoclessError::StateFailed(failure)) => failure,
+        other => panic!("expected a state failure, got {:?}", other),
+    };
+    assert_eq!(failure.error_type, "String");
+    assert_eq!(failure.message, "upstream API returned 503");
+
+    let saved = store
+        .get_execution_results("bootstrap-failure-exec")
+        .await
+        .unwrap()
+        .unwrap();
+    let saved_failure: StateFailure =
+        from_value(saved.results.errors["Flaky_Lookup"].clone()).unwrap();
+    assert_eq!(saved_failure, failure);
+    assert!(saved.results.results.get("Flaky_Lookup").is_none());
+}
+
+static FLAKY_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+async fn fails_once(_params: Value) -> Result<Value, String> {
+    match FLAKY_CALLS.fetch_add(1, Ordering::SeqCst) {
+        0 => Err("upstream API returned 503".to_string()),
+        _ => Ok(json!({ "status": "ok" })),
+    }
+}
+
+#[tokio::test]
+async fn test_socless_bootstrap_records_each_attempt() {
+    let store = memory_store().await;
+    seed_execution(store, "attempts-exec").await;
+
+    for request_id in ["request-1", "request-2"] {
+        let event = json!({
+            "execution_id": "attempts-exec",
+            "State_Config": { "Name": "Flaky_Lookup", "Parameters": {} }
+        });
+        let mut context = Context::default();
+        context.request_id = request_id.to_string();
+        let _ = socless_bootstrap(event, context, fails_once, false).await;
+    }
+
+    let saved = store
+        .get_execution_results("attempts-exec")
+        .await
+        .unwrap()
+        .unwrap();
+    let attempts = &saved.results.attempts["Flaky_Lookup"];
+    assert_eq!(attempts.len(), 2);
+
+    assert_eq!(attempts[0].attempt, 1);
+    assert_eq!(attempts[0].request_id, "request-1");
+    assert_eq!(
+        attempts[0].error.as_ref().unwrap().message,
+        "upstream API returned 503"
+    );
+    assert!(attempts[0].result.is_none());
+
+    assert_eq!(attempts[1].attempt, 2);
+    assert_eq!(attempts[1].request_id, "request-2");
+    assert_eq!(
+        attempts[1].result,
+        Some(ResultDigest::of(&json!({ "status": "ok" })))
+    );
+    assert!(attempts[1].error.is_none());
+    assert!(attempts[1].started_at <= attempts[1].ended_at);
+}
+
+#[derive(Deserialize)]
+struct ContextParams {
+    context: SoclessContext,
+}
+
+async fn echo_request_id(params: ContextParams) -> Result<Value, String> {
+    let lambda = params.context.lambda.ok_or("no lambda in context")?;
+    Ok(json!({ "request_id": lambda.request_id }))
+}
+
+#[tokio::test]
+async fn test_socless_bootstrap_passes_lambda_context_and_saves_metadata() {
+    let store = memory_store().await;
+    seed_execution(store, "lambda-context-exec").await;
+
+    let event = json!({
+        "execution_id": "lambda-context-exec",
+        "State_Config": { "Name": "Echo_Request", "Parameters": {} }
+    });
+    let mut context = Context::default();
+    context.request_id = "request-1".to_string();
+    context.invoked_function_arn =
+        "arn:aws:lambda:us-east-1:12345678901:function:echo_request".to_string();
+
+    let output = socless_bootstrap(event, context, echo_request_id, true)
+        .await
+        .unwrap();
+    assert_eq!(output, json!({"request_id": "request-1"}));
+
+    let saved = store
+        .get_execution_results("lambda-context-exec")
+        .await
+        .unwrap()
+        .unwrap();
+    let metadata = &saved.results.metadata["Echo_Request"];
+    assert_eq!(metadata.lambda.request_id, "request-1");
+    assert_eq!(
+        metadata.lambda.function_arn,
+        "arn:aws:lambda:us-east-1:12345678901:function:echo_request"
+    );
+    assert!(!metadata.saved_at.is_empty());
+}
+
+async fn waits_for_cancellation(_params: Value) -> Result<Value, String> {
+    cancellation_token().cancelled().await;
+    Err("stopped before the Lambda timeout".to_string())
+}
+
+#[tokio::test]
+async fn test_socless_bootstrap_cancels_handler_before_deadline() {
+    let store = memory_store().await;
+    seed_execution(store, "deadline-exec").await;
+
+    let event = json!({
+        "execution_id": "deadline-exec",
+        "State_Config": { "Name": "Slow_Lookup", "Parameters": {} }
+    });
+    let deadline = SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+        + DEADLINE_MARGIN
+        + Duration::from_millis(100);
+    let mut context = Context::default();
+    context.deadline = deadline.as_millis() as u64;
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        socless_bootstrap(event, context, waits_for_cancellation, false),
+    )
+    .await
+    .expect("handler not cancelled before the deadline");
+    assert!(matches!(result, Err(SoclessError::StateFailed(_))));
+
+    let saved = store
+        .get_execution_results("deadline-exec")
+        .await
+        .unwrap()
+        .unwrap();
+    let saved_failure: StateFailure =
+        from_value(saved.results.errors["Slow_Lookup"].clone()).unwrap();
+    assert_eq!(saved_failure.message, "stopped before the Lambda timeout");
+}
+
+async fn issue_token(_params: Value) -> Result<Value, String> {
+    Ok(json!({ "user": "sterling", "session": { "api_token": "abc123" } }))
+}
+
+#[tokio::test]
+async fn test_socless_bootstrap_redacts_saved_results() {
+    let store = memory_store().await;
+    seed_execution(store, "redaction-exec").await;
+
+    let event = json!({
+        "execution_id": "redaction-exec",
+        "State_Config": { "Name": "Issue_Token", "Parameters": {} }
+    });
+    let output = socless_bootstrap(event, Context::default(), issue_token, false)
+        .await
+        .unwrap();
+    assert_eq!(output["session"]["api_token"], "abc123");
+
+    let saved = store
+        .get_execution_results("redaction-exec")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        saved.results.results["Issue_Token"],
+        json!({ "user": "sterling", "session": { "api_token": "[REDACTED]" } })
+    );
+    assert_eq!(
+        saved.results.attempts["Issue_Token"][0].result,
+        Some(ResultDigest::of(&saved.results.results["Issue_Token"]))
+    );
+}
+
+async fn fetch_large_logs(_params: Value) -> Result<Value, String> {
+    Ok(json!({ "log": "a".repeat(DEFAULT_OFFLOAD_THRESHOLD_BYTES * 6 / 10) }))
+}
+
+#[tokio::test]
+async fn test_socless_bootstrap_retries_of_large_results_fit_the_results_item() {
+    let store = memory_store().await;
+    seed_execution(store, "large-retries-exec").await;
+
+    // five full copies next to the saved result would be over DynamoDB's item limit
+    for _ in 0..5 {
+        let event = json!({
+            "execution_id": "large-retries-exec",
+            "State_Config": { "Name": "Fetch_Logs", "Parameters": {} }
+        });
+        socless_bootstrap(event, Context::default(), fetch_large_logs, false)
+            .await
+            .unwrap();
+    }
+
+    let saved = store
+        .get_execution_results("large-retries-exec")
+        .await
+        .unwrap()
+        .unwrap();
+    let attempts = &saved.results.attempts["Fetch_Logs"];
+    assert_eq!(attempts.len(), 5);
+    for attempt in attempts {
+        let digest = attempt.result.as_ref().unwrap();
+        assert!(digest.vault_ref.is_none());
+        assert!(digest.bytes > DEFAULT_OFFLOAD_THRESHOLD_BYTES * 6 / 10);
+    }
+    assert!(serde_json::to_vec(&saved).unwrap().len() < DYNAMO_ITEM_LIMIT_BYTES);
+}
+
+async fn fetch_logs(_params: Value) -> Result<Value, String> {
+    Ok(json!({ "log": "a".repeat(DEFAULT_OFFLOAD_THRESHOLD_BYTES) }))
+}
+
+#[tokio::test]
+async fn test_socless_bootstrap_offloads_oversized_results_to_the_vault() {
+    let store = memory_store().await;
+    seed_execution(store, "offload-exec").await;
+
+    let event = json!({
+        "execution_id": "offload-exec",
+        "State_Config": { "Name": "Fetch_Logs", "Parameters": {} }
+    });
+    let output = socless_bootstrap(event, Context::default(), fetch_logs, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        output["log"].as_str().unwrap().len(),
+        DEFAULT_OFFLOAD_THRESHOLD_BYTES
+    );
+
+    let saved = store
+        .get_execution_results("offload-exec")
+        .await
+        .unwrap()
+        .unwrap();
+    let vault_id = saved.results.results["Fetch_Logs"]
+        .as_str()
+        .expect("oversized result not offloaded");
+    assert!(vault_id.starts_with("vault:"));
+
+    let context: SoclessContext = from_value(json!(saved.results)).unwrap();
+    let log = resolve_json_path("$.results.Fetch_Logs.log", &context)
+        .await
+        .unwrap();
+    assert_eq!(log.as_str().unwrap().len(), DEFAULT_OFFLOAD_THRESHOLD_BYTES);
+}
+
+/// Fake credentials for Step Functions, which [`memory_store`] points at a closed port so
+/// playbook starts and task callbacks fail fast.
+fn use_unreachable_aws() {
+    std::env::set_var("AWS_REGION", "us-east-1");
+    std::env::set_var("AWS_ACCESS_KEY_ID", "test");
+    std::env::set_var("AWS_SECRET_ACCESS_KEY", "test");
+}
+
+#[tokio::test]
+async fn test_create_events_stores_events_and_results() {
+    use_unreachable_aws();
+
+    let store = memory_store().await;
+    let mut context = Context::default();
+    context.invoked_function_arn =
+        "arn:aws:lambda:us-east-1:12345678901:function:create_events".to_string();
+
+    let statuses = create_events(
+        SoclessEventBatch {
+            event_type: "memory_store_test_event".to_string(),
+            playbook: "MemoryStorePlaybook".to_string(),
+            details: vec![json!({"user": "sterling"}), json!({"user": "malory"})],
+            ..Default::default()
+        },
+        context,
+    )
+    .await
+    .unwrap();
+    assert_eq!(statuses.len(), 2);
+
+    let events: Vec<EventTableItem> = store
+        .event_items()
+        .into_iter()
+        .filter(|event| event.event_type == "memory_store_test_event")
+        .collect();
+    assert_eq!(events.len(), 2);
+
+    for event in events {
+        let results = store
+            .execution_results_items()
+            .into_iter()
+            .find(|item| item.investigation_id == event.investigation_id)
+            .expect("no results item saved for event");
+        assert_eq!(results.results.artifacts.event.id, event.id);
+    }
+}
+
+fn human_interaction_context(execution_id: &str, state_name: &str) -> SoclessContext {
+    from_value(json!({
+        "execution_id": execution_id,
+        "artifacts": {
+            "event": { "investigation_id": format!("{}-investigation", execution_id) },
+            "execution_id": execution_id
+        },
+        "task_token": "mock-task-token",
+        "state_name": state_name
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_init_human_interaction_saves_message_response() {
+    let store = memory_store().await;
+    seed_execution(store, "human-exec").await;
+
+    let message_id = init_human_interaction(
+        human_interaction_context("human-exec", "Ask_User"),
+        "are you sure?",
+        None,
+    )
+    .await
+    .unwrap();
+
+    let response = store
+        .get_message_response(&message_id)
+        .await
+        .unwrap()
+        .expect("message response not saved");
+    assert_eq!(response.execution_id, "human-exec");
+    assert_eq!(response.receiver, "Ask_User");
+    assert_eq!(response.await_token, "mock-task-token");
+    assert!(!response.fulfilled);
+}
+
+#[tokio::test]
+async fn test_end_human_interaction_saves_response_before_task_callback() {
+    use_unreachable_aws();
+
+    let store = memory_store().await;
+    seed_execution(store, "human-end-exec").await;
+
+    let message_id = init_human_interaction(
+        human_interaction_context("human-end-exec", "Ask_User"),
+        "are you sure?",
+        None,
+    )
+    .await
+    .unwrap();
+
+    let result = end_human_interaction(message_id, json!({"approved": true})).await;
+    assert!(matches!(result, Err(SoclessError::StepFunctions(_))));
+
+    let saved = store
+        .get_execution_results("human-end-exec")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(saved.results.results["Ask_User"], json!({"approved": true}));
+}
+
+#[tokio::test]
+async fn test_end_human_interaction_unknown_message_id() {
+    memory_store().await;
+
+    let result = end_human_interaction("not-a-message-id".to_string(), json!({})).await;
+    assert!(matches!(result, Err(SoclessError::NotFound { .. })));
+}